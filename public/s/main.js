@@ -29,4 +29,9 @@ for(var i = 0; i < els.length; i++){
 	els[i].onmouseover = function() { mo_tab(this) };
 	els[i].onmouseout = function() { me_tab(this) };
 	els[i].onclick = function() { mc_tab(this) };
-}
\ No newline at end of file
+}
+
+// Reconverting only changes the options that were touched, the others stay as recorded
+document.addEventListener("change", function(e) {
+	e.target.setAttribute("data-changed", "");
+});
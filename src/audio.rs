@@ -0,0 +1,524 @@
+use std::{
+    collections::HashMap,
+    io::{Cursor, ErrorKind},
+    path::Path,
+    rc::Rc,
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Result};
+use serde_derive::{Deserialize, Serialize};
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::DecoderOptions,
+    errors::Error as DecodeError,
+    formats::FormatOptions,
+    io::MediaSourceStream,
+    meta::{Metadata, MetadataOptions},
+    probe::{Hint, ProbeResult},
+};
+
+/// Samples quieter than this count as silence, about -60dB
+const SILENCE_LEVEL: f32 = 0.001;
+
+/// Length of the windows used to stretch audio in ms
+const STRETCH_WINDOW_MS: f64 = 40.;
+/// How far a window may move to line up with the previous one in ms
+const STRETCH_SEARCH_MS: f64 = 5.;
+
+/// Format of the audio file in the converted map
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AudioFormat {
+    /// Copy the osu! audio file unchanged
+    Original,
+    /// Decode and write 16 bit PCM WAV
+    Wav,
+}
+
+/// Decoded audio with interleaved samples between -1 and 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pcm {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub samples: Vec<f32>,
+}
+
+impl Pcm {
+    /// Decodes MP3, OGG Vorbis or WAV. `file_name` is only used as a hint
+    /// for the container format.
+    pub fn decode(data: &Arc<[u8]>, file_name: &str) -> Result<Pcm> {
+        let mut format = probe(data, file_name)?.format;
+        let track = format
+            .default_track()
+            .ok_or_else(|| anyhow!("No audio track in {file_name:?}"))?;
+        let track_id = track.id;
+        let mut decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())?;
+
+        let mut pcm = Pcm {
+            sample_rate: track.codec_params.sample_rate.unwrap_or(44100),
+            channels: track.codec_params.channels.map_or(2, |c| c.count() as u16),
+            samples: Vec::new(),
+        };
+        loop {
+            let packet = match format.next_packet() {
+                Ok(packet) => packet,
+                Err(DecodeError::IoError(e)) if e.kind() == ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            };
+            if packet.track_id() != track_id {
+                continue;
+            }
+
+            match decoder.decode(&packet) {
+                Ok(decoded) => {
+                    let spec = *decoded.spec();
+                    pcm.sample_rate = spec.rate;
+                    pcm.channels = spec.channels.count() as u16;
+                    let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+                    buffer.copy_interleaved_ref(decoded);
+                    pcm.samples.extend_from_slice(buffer.samples());
+                }
+                // A broken frame shouldn't throw away the whole song
+                Err(DecodeError::DecodeError(e)) => log::warn!("Skipped audio frame: {e}"),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        if pcm.samples.is_empty() {
+            return Err(anyhow!("{file_name:?} contains no audio"));
+        }
+        Ok(pcm)
+    }
+
+    /// Length in ms
+    pub fn length(&self) -> f64 {
+        (self.samples.len() / self.channels.max(1) as usize) as f64 * 1000.
+            / self.sample_rate as f64
+    }
+
+    fn frames_in(&self, ms: f64) -> usize {
+        (ms / 1000. * self.sample_rate as f64).round() as usize
+    }
+
+    /// Length of the silence at the start in ms
+    pub fn leading_silence(&self) -> f64 {
+        let first_sound = self
+            .samples
+            .iter()
+            .position(|s| s.abs() > SILENCE_LEVEL)
+            .unwrap_or(self.samples.len());
+        (first_sound / self.channels as usize) as f64 * 1000. / self.sample_rate as f64
+    }
+
+    /// Adds `ms` of silence at the start
+    pub fn pad_start(&mut self, ms: f64) {
+        let samples = self.frames_in(ms) * self.channels as usize;
+        self.samples.splice(0..0, std::iter::repeat_n(0., samples));
+    }
+
+    /// Removes the first `ms` of audio
+    pub fn trim_start(&mut self, ms: f64) {
+        let samples = (self.frames_in(ms) * self.channels as usize).min(self.samples.len());
+        self.samples.drain(..samples);
+    }
+
+    fn sample(&self, frame: usize, channel: usize) -> f32 {
+        self.samples
+            .get(frame * self.channels as usize + channel)
+            .copied()
+            .unwrap_or(0.)
+    }
+
+    /// Plays the audio `rate` times as fast, which changes the pitch as well
+    pub fn resample(&mut self, rate: f64) {
+        let channels = self.channels as usize;
+        let frames = (self.samples.len() / channels) as f64 / rate;
+        let mut samples = Vec::with_capacity(frames as usize * channels);
+        for i in 0..frames as usize {
+            let pos = i as f64 * rate;
+            let frame = pos as usize;
+            let fraction = (pos - frame as f64) as f32;
+            for c in 0..channels {
+                let a = self.sample(frame, c);
+                let b = self.sample(frame + 1, c);
+                samples.push(a + (b - a) * fraction);
+            }
+        }
+        self.samples = samples;
+    }
+
+    /// Plays the audio `rate` times as fast without changing the pitch, using
+    /// overlap-add of windows aligned to continue the previous one (WSOLA)
+    pub fn time_stretch(&mut self, rate: f64) {
+        let channels = self.channels as usize;
+        let window = self.frames_in(STRETCH_WINDOW_MS).max(2);
+        let hop = window / 2;
+        let search = self.frames_in(STRETCH_SEARCH_MS);
+        let hann: Vec<f32> = (0..window)
+            .map(|i| 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / window as f32).cos())
+            .collect();
+        // Padded with silence, so windows near the end can be compared too
+        let in_frames = self.samples.len() / channels;
+        let mut mono: Vec<f32> = self
+            .samples
+            .chunks(channels)
+            .map(|frame| frame.iter().sum())
+            .collect();
+        mono.resize(in_frames + search + 2 * window, 0.);
+
+        let frames = (in_frames as f64 / rate) as usize;
+        let mut samples = vec![0.; (frames + window) * channels];
+        let mut previous: Option<usize> = None;
+        for out_frame in (0..frames).step_by(hop) {
+            let target = (out_frame as f64 * rate) as usize;
+            // Pick the window start near the target that best matches what
+            // the previous window would have continued with
+            let start = match previous {
+                None => target,
+                Some(previous) => {
+                    let expected = &mono[previous + hop..previous + 2 * hop];
+                    let similarity = |start: usize| -> f32 {
+                        mono[start..start + hop]
+                            .iter()
+                            .zip(expected)
+                            .step_by(4)
+                            .map(|(a, b)| a * b)
+                            .sum()
+                    };
+                    (target.saturating_sub(search)..=target + search)
+                        .step_by(2)
+                        .map(|start| (similarity(start), start))
+                        .max_by(|a, b| a.0.total_cmp(&b.0))
+                        .map_or(target, |(_, start)| start)
+                }
+            };
+            for (i, w) in hann.iter().enumerate() {
+                for c in 0..channels {
+                    samples[(out_frame + i) * channels + c] += self.sample(start + i, c) * w;
+                }
+            }
+            previous = Some(start);
+        }
+        samples.truncate(frames * channels);
+        self.samples = samples;
+    }
+
+    /// Copy with the given sample rate and number of channels
+    pub fn converted(&self, sample_rate: u32, channels: u16) -> Pcm {
+        let mut pcm = self.clone();
+        if sample_rate != self.sample_rate {
+            pcm.resample(self.sample_rate as f64 / sample_rate as f64);
+            pcm.sample_rate = sample_rate;
+        }
+        if channels != self.channels {
+            let frames = pcm.samples.len() / pcm.channels as usize;
+            pcm.samples = (0..frames)
+                .flat_map(|f| {
+                    let pcm = &pcm;
+                    (0..channels as usize).map(move |c| {
+                        if pcm.channels == 1 {
+                            pcm.sample(f, 0)
+                        } else if channels == 1 {
+                            (0..pcm.channels as usize)
+                                .map(|c| pcm.sample(f, c))
+                                .sum::<f32>()
+                                / pcm.channels as f32
+                        } else {
+                            pcm.sample(f, c % pcm.channels as usize)
+                        }
+                    })
+                })
+                .collect();
+            pcm.channels = channels;
+        }
+        pcm
+    }
+
+    /// Adds `other` at `ms` with `volume` from 0 to 1. `other` needs the same
+    /// sample rate and channels, and is cut off at the end.
+    pub fn mix(&mut self, other: &Pcm, ms: f64, volume: f32) {
+        if ms < 0. {
+            return;
+        }
+        let start = self.frames_in(ms) * self.channels as usize;
+        for (s, o) in self.samples.iter_mut().skip(start).zip(&other.samples) {
+            *s += o * volume;
+        }
+    }
+
+    /// Encodes as 16 bit PCM WAV
+    pub fn to_wav(&self) -> Result<Vec<u8>> {
+        let spec = hound::WavSpec {
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut cursor = Cursor::new(Vec::new());
+        {
+            let mut writer = hound::WavWriter::new(&mut cursor, spec)?;
+            for s in &self.samples {
+                writer.write_sample((s.clamp(-1., 1.) * i16::MAX as f32) as i16)?;
+            }
+            writer.finalize()?;
+        }
+        Ok(cursor.into_inner())
+    }
+}
+
+/// The stream has to own its source, sharing `data` saves copying the file
+fn probe(data: &Arc<[u8]>, file_name: &str) -> Result<ProbeResult> {
+    let stream = MediaSourceStream::new(Box::new(Cursor::new(data.clone())), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = Path::new(file_name).extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+    Ok(symphonia::default::get_probe().format(
+        &hint,
+        stream,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?)
+}
+
+/// Length of an audio file in ms. Taken from the header if it has one,
+/// otherwise the whole file is decoded.
+pub fn length(data: &Arc<[u8]>, file_name: &str) -> Result<f64> {
+    let probed = probe(data, file_name)?;
+    if let Some(params) = probed.format.default_track().map(|t| &t.codec_params) {
+        if let (Some(frames), Some(rate)) = (params.n_frames, params.sample_rate) {
+            return Ok(frames as f64 * 1000. / rate as f64);
+        }
+    }
+    Ok(Pcm::decode(data, file_name)?.length())
+}
+
+/// Image data of the cover art in the tags of an audio file
+pub fn cover_art(data: &Arc<[u8]>, file_name: &str) -> Option<Vec<u8>> {
+    let mut probed = probe(data, file_name).ok()?;
+    // Tags before the audio, like ID3v2, are read by the probe
+    let visual = |metadata: Option<Metadata>| {
+        metadata
+            .and_then(|m| m.current().and_then(|r| r.visuals().first().cloned()))
+            .map(|v| v.data.into_vec())
+    };
+    visual(probed.metadata.get()).or_else(|| visual(Some(probed.format.metadata())))
+}
+
+/// Changes to the audio, only possible when it is re-encoded
+#[derive(Debug, Clone)]
+pub struct Edits {
+    /// Playback speed, 1 to keep it
+    pub rate: f64,
+    /// Keeps the pitch when changing the rate, otherwise it changes with the speed
+    pub keep_pitch: bool,
+    /// Silence added before the audio in ms
+    pub lead_in: f64,
+    /// Removes silence at the start of the audio
+    pub trim_silence: bool,
+    /// Sounds mixed into the audio before the rate changes
+    pub hitsounds: Vec<Hitsound>,
+}
+
+/// A sample played at a time in the original audio
+#[derive(Debug, Clone)]
+pub struct Hitsound {
+    pub time: f64,
+    /// From 0 to 1
+    pub volume: f32,
+    pub sample: Rc<Pcm>,
+}
+
+impl Default for Edits {
+    fn default() -> Self {
+        Edits {
+            rate: 1.,
+            keep_pitch: true,
+            lead_in: 0.,
+            trim_silence: false,
+            hitsounds: Vec::new(),
+        }
+    }
+}
+
+impl Edits {
+    pub fn is_empty(&self) -> bool {
+        self.rate == 1. && self.lead_in <= 0. && !self.trim_silence && self.hitsounds.is_empty()
+    }
+}
+
+/// Audio file after transcoding
+#[derive(Debug, Clone)]
+pub struct Transcoded {
+    pub data: Arc<[u8]>,
+    pub file_name: String,
+    /// Ms everything in the song has to move by to stay in sync with the audio
+    pub shift: f64,
+}
+
+/// Converts the audio file `data` called `file_name` to `format`. `edits`
+/// are ignored if the format is `Original`. The rate is applied first, so
+/// the lead-in and the shift are in ms of the new audio.
+pub fn transcode(
+    data: &Arc<[u8]>,
+    file_name: &str,
+    format: AudioFormat,
+    edits: &Edits,
+) -> Result<Transcoded> {
+    match format {
+        AudioFormat::Original => Ok(Transcoded {
+            data: data.clone(),
+            file_name: file_name.to_string(),
+            shift: 0.,
+        }),
+        AudioFormat::Wav => {
+            let mut pcm = Pcm::decode(data, file_name)?;
+            // Samples are usually shared by many hitsounds, so only convert them once
+            let mut converted = HashMap::new();
+            for h in &edits.hitsounds {
+                let sample = converted
+                    .entry(Rc::as_ptr(&h.sample))
+                    .or_insert_with(|| h.sample.converted(pcm.sample_rate, pcm.channels));
+                pcm.mix(sample, h.time, h.volume);
+            }
+            if edits.rate != 1. {
+                if edits.keep_pitch {
+                    pcm.time_stretch(edits.rate);
+                } else {
+                    pcm.resample(edits.rate);
+                }
+            }
+            let mut shift = 0.;
+            if edits.trim_silence {
+                let silence = pcm.leading_silence();
+                pcm.trim_start(silence);
+                shift -= silence;
+            }
+            pcm.pad_start(edits.lead_in.max(0.));
+            shift += edits.lead_in.max(0.);
+
+            let name = Path::new(file_name).with_extension("wav");
+            Ok(Transcoded {
+                data: pcm.to_wav()?.into(),
+                file_name: name.to_string_lossy().into_owned(),
+                shift,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wav_round_trip() -> Result<()> {
+        let pcm = Pcm {
+            sample_rate: 8000,
+            channels: 2,
+            samples: (0..1600).map(|i| (i as f32 / 100.).sin() * 0.5).collect(),
+        };
+        let wav = transcode(
+            &pcm.to_wav()?.into(),
+            "audio.wav",
+            AudioFormat::Wav,
+            &Edits::default(),
+        )?;
+        assert_eq!(wav.file_name, "audio.wav");
+        assert_eq!(wav.shift, 0.);
+
+        let decoded = Pcm::decode(&wav.data, &wav.file_name)?;
+        assert_eq!(decoded.sample_rate, 8000);
+        assert_eq!(decoded.channels, 2);
+        assert_eq!(decoded.samples.len(), pcm.samples.len());
+        for (a, b) in decoded.samples.iter().zip(&pcm.samples) {
+            assert!((a - b).abs() < 0.001);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn renames_to_wav() -> Result<()> {
+        let pcm = Pcm {
+            sample_rate: 8000,
+            channels: 1,
+            samples: vec![0.; 800],
+        };
+        let wav = transcode(
+            &pcm.to_wav()?.into(),
+            "song.mp3",
+            AudioFormat::Wav,
+            &Edits::default(),
+        )?;
+        assert_eq!(wav.file_name, "song.wav");
+        Ok(())
+    }
+
+    #[test]
+    fn reads_length() -> Result<()> {
+        let pcm = Pcm {
+            sample_rate: 8000,
+            channels: 2,
+            samples: vec![0.; 24000],
+        };
+        assert_eq!(pcm.length(), 1500.);
+        assert_eq!(length(&pcm.to_wav()?.into(), "audio.wav")?, 1500.);
+        Ok(())
+    }
+
+    /// Sign changes per second
+    fn zero_crossings(pcm: &Pcm) -> f64 {
+        let crossings = pcm
+            .samples
+            .windows(2)
+            .filter(|w| (w[0] < 0.) != (w[1] < 0.))
+            .count();
+        crossings as f64 / (pcm.samples.len() as f64 / pcm.sample_rate as f64)
+    }
+
+    #[test]
+    fn rate_change() {
+        let pcm = Pcm {
+            sample_rate: 8000,
+            channels: 1,
+            samples: (0..16000)
+                .map(|i| (i as f32 * 440. * std::f32::consts::TAU / 8000.).sin())
+                .collect(),
+        };
+
+        let mut stretched = pcm.clone();
+        stretched.time_stretch(1.5);
+        assert_eq!(stretched.samples.len(), 10666);
+        assert!((zero_crossings(&stretched) / zero_crossings(&pcm) - 1.).abs() < 0.05);
+
+        let mut resampled = pcm.clone();
+        resampled.resample(1.5);
+        assert_eq!(resampled.samples.len(), 10666);
+        assert!((zero_crossings(&resampled) / zero_crossings(&pcm) - 1.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn trims_silence_and_adds_lead_in() -> Result<()> {
+        let mut samples = vec![0.; 400];
+        samples.extend(vec![0.5; 400]);
+        let pcm = Pcm {
+            sample_rate: 8000,
+            channels: 1,
+            samples,
+        };
+        let edits = Edits {
+            lead_in: 100.,
+            trim_silence: true,
+            ..Default::default()
+        };
+        let wav = transcode(&pcm.to_wav()?.into(), "audio.wav", AudioFormat::Wav, &edits)?;
+        // 50ms of silence removed, 100ms added
+        assert_eq!(wav.shift, 50.);
+
+        let decoded = Pcm::decode(&wav.data, &wav.file_name)?;
+        assert_eq!(decoded.samples.len(), 1200);
+        assert_eq!(decoded.leading_silence(), 100.);
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+use std::{path::Path, sync::Arc};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    audio::Pcm,
+    onset::Analysis,
+    sanitize,
+    te::{self, Note, NoteType},
+    thumb::{self, ThumbMode},
+};
+
+/// Louder parts count as sustained while they stay above this part of the
+/// loudness at the onset
+const SUSTAIN_LEVEL: f32 = 0.6;
+/// Width and height of the generated thumbnail
+const THUMB_SIZE: u32 = 512;
+
+#[derive(Debug, Clone)]
+pub struct AutoOptions {
+    /// Upper limit of the average note density
+    pub notes_per_second: f64,
+    /// Notes are snapped to 1/divisor beats
+    pub divisor: u32,
+    /// Sustained sounds at least this many beats long become holds
+    pub min_hold_beats: f64,
+}
+
+impl Default for AutoOptions {
+    fn default() -> Self {
+        AutoOptions {
+            notes_per_second: 3.,
+            divisor: 4,
+            min_hold_beats: 1.,
+        }
+    }
+}
+
+/// Charts the audio file `audio` called `file_name` without an osu! map.
+/// Artist and title are taken from a file name like "Artist - Title.mp3".
+pub fn autochart(audio: Arc<[u8]>, file_name: &str, options: &AutoOptions) -> Result<te::Map> {
+    let pcm = Pcm::decode(&audio, file_name)?;
+    let analysis = Analysis::new(&pcm);
+    let (bpm, offset) = analysis
+        .estimate_tempo()
+        .ok_or_else(|| anyhow!("Could not find a beat in the audio"))?;
+    let beat_length = 60000. / bpm;
+    log::trace!("Detected {bpm} BPM with the first beat at {offset:.0}ms");
+
+    // Keep the strongest onsets the density allows, snapped to the grid
+    let mut onsets: Vec<_> = analysis.onsets.iter().collect();
+    onsets.sort_by(|a, b| b.strength.total_cmp(&a.strength));
+    onsets.truncate((analysis.length / 1000. * options.notes_per_second).ceil() as usize);
+    onsets.sort_by(|a, b| a.time.total_cmp(&b.time));
+    let step = beat_length / options.divisor.max(1) as f64;
+    let mut snapped: Vec<(f64, f32)> = Vec::new();
+    for o in onsets {
+        let time = offset + ((o.time - offset) / step).round() * step;
+        if time >= offset && snapped.last().is_none_or(|(t, _)| time - t > 1.) {
+            snapped.push((time, o.centroid));
+        }
+    }
+
+    // Lower sounds go on the left disc, brighter ones on the right
+    let mut centroids: Vec<f32> = snapped.iter().map(|(_, c)| *c).collect();
+    centroids.sort_by(|a, b| a.total_cmp(b));
+    let median = centroids.get(centroids.len() / 2).copied().unwrap_or(0.);
+    let times = |left: bool| -> Vec<f64> {
+        snapped
+            .iter()
+            .filter(|(_, c)| (*c < median) == left)
+            .map(|(t, _)| *t)
+            .collect()
+    };
+    let (left, right) = (times(true), times(false));
+    let left_notes = disc_notes(&left, &analysis, beat_length, step, options);
+    let right_notes = disc_notes(&right, &analysis, beat_length, step, options);
+
+    let stem = Path::new(file_name)
+        .file_stem()
+        .map_or_else(String::new, |s| s.to_string_lossy().into_owned());
+    let (artist, title) = match stem.split_once(" - ") {
+        Some((artist, title)) => (sanitize::text(artist), sanitize::text(title)),
+        None => (String::new(), sanitize::text(&stem)),
+    };
+
+    let thumb = thumb::fallback(
+        &audio,
+        file_name,
+        &title,
+        &artist,
+        "Auto",
+        ThumbMode::Centre,
+        THUMB_SIZE,
+    );
+    let data = te::MapData {
+        mapper_name: "Auto".to_string(),
+        audio_file_name: sanitize::file_name(file_name),
+        thumbnail_file_name: "thumb.png".to_string(),
+        song_file_name: sanitize::file_name(&format!("{title}.song")),
+        display_name: title,
+        artist,
+        difficulty_name: "Auto".to_string(),
+        preview_time_seconds: (analysis.length * 0.3 / 1000.).round(),
+        bpm,
+        difficulty_settings: te::DifficultySettings {
+            note_appear_time: 0.5,
+            rotation_speed: 125.,
+            health_drain_per_second: 5.,
+        },
+        first_beat_offset_in_ms: offset.round() as i64,
+        timing_points: Vec::new(),
+        right_disc_notes: te::format_notes(&right_notes),
+        left_disc_notes: te::format_notes(&left_notes),
+        song_events: Vec::new(),
+        special_sections: Vec::new(),
+        breaks: Vec::new(),
+        additional_difficulties: Vec::new(),
+    };
+
+    Ok(te::Map {
+        data,
+        audio,
+        thumb,
+        source: None,
+    })
+}
+
+/// Notes at `times` on one disc, with holds where the sound is sustained
+/// until the next note
+fn disc_notes(
+    times: &[f64],
+    analysis: &Analysis,
+    beat_length: f64,
+    step: f64,
+    options: &AutoOptions,
+) -> Vec<Note> {
+    let mut notes = Vec::new();
+    for (i, time) in times.iter().enumerate() {
+        let next = times.get(i + 1).copied().unwrap_or(analysis.length);
+        // The loudest point right after the onset, the attack takes a moment
+        let level = (0..5)
+            .map(|f| analysis.rms_at(time + f as f64 * analysis.frame_ms))
+            .fold(0., f32::max);
+        let mut end = *time;
+        while end + analysis.frame_ms < next && analysis.rms_at(end) >= level * SUSTAIN_LEVEL {
+            end += analysis.frame_ms;
+        }
+        // Snap down and leave a gap before the next note
+        let end = (time + ((end - time) / step).floor() * step).min(next - step);
+
+        if end - time >= options.min_hold_beats * beat_length {
+            notes.push(Note::new(*time, NoteType::HoldStart));
+            notes.push(Note::new(end, NoteType::HoldEnd));
+        } else {
+            notes.push(Note::new(*time, NoteType::Normal));
+        }
+    }
+    notes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{onset, te::Disc};
+
+    #[test]
+    fn charts_click_track() {
+        let wav = onset::tests::click_track(128., 230., 20.).to_wav().unwrap();
+        let map = autochart(wav.into(), "Someone - Clicks.wav", &AutoOptions::default()).unwrap();
+        assert_eq!(map.data.artist, "Someone");
+        assert_eq!(map.data.display_name, "Clicks");
+        assert_eq!(map.data.bpm, 128.);
+
+        let notes = [
+            map.data.notes(Disc::Left).unwrap(),
+            map.data.notes(Disc::Right).unwrap(),
+        ]
+        .concat();
+        assert!(notes.len() >= 40);
+        let beat = 60000. / 128.;
+        for n in notes {
+            let phase = (n.time - map.data.first_beat_offset_in_ms as f64).rem_euclid(beat / 4.);
+            assert!(
+                phase.min(beat / 4. - phase) <= 1.,
+                "{} not on the grid",
+                n.time
+            );
+        }
+    }
+}
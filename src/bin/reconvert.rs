@@ -0,0 +1,67 @@
+//! Converts a Twin Edge map zip or installed song folder again from the osu!
+//! map it was made from, with some options changed, and overwrites it
+//!
+//! ```text
+//! reconvert <map zip or song folder> <osu! Songs folder> [--deflate=<level>] [<option>=<value>...]
+//! ```
+//!
+//! Options are the fields of the recorded conversion options, e.g.
+//! `slider_beat_limit=2` or `overlap_policy=Drop`. Everything else is
+//! converted like the last time.
+
+use std::{path::PathBuf, process::ExitCode};
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+use osu_to_te::{
+    rating, reconvert,
+    te::{Compression, ZipOptions},
+};
+
+const USAGE: &str = "Usage: reconvert <map zip or song folder> <osu! Songs folder> [--deflate=<level>] [<option>=<value>...]";
+
+fn main() -> ExitCode {
+    match run(std::env::args().skip(1)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let path = PathBuf::from(args.next().ok_or_else(|| anyhow!("No map given"))?);
+    let songs_dir = PathBuf::from(
+        args.next()
+            .ok_or_else(|| anyhow!("No Songs folder given"))?,
+    );
+
+    let mut zip_options = ZipOptions::default();
+    let mut changes = serde_json::Map::new();
+    for arg in args {
+        if let Some(level) = arg.strip_prefix("--deflate=") {
+            let level = level
+                .parse()
+                .ok()
+                .filter(|l| (0..=9).contains(l))
+                .ok_or_else(|| anyhow!("Compression level has to be a number from 0 to 9"))?;
+            zip_options.compression = Compression::Deflated(level);
+        } else {
+            let (key, value) = reconvert::parse_change(&arg)?;
+            changes.insert(key, value);
+        }
+    }
+
+    let (map, report) =
+        reconvert::reconvert_path(&path, &songs_dir, &Value::Object(changes), zip_options)?;
+    println!("Reconverted {}. {report}", path.display());
+    for (name, rating) in rating::rate_all(&map.data)? {
+        println!("{name}: {rating}");
+    }
+    for w in &report.warnings {
+        println!("Warning: {w}");
+    }
+    Ok(())
+}
@@ -0,0 +1,42 @@
+//! Checks a Twin Edge map zip or installed song folder for problems
+//!
+//! ```text
+//! validate <map zip or song folder>
+//! ```
+
+use std::{path::PathBuf, process::ExitCode};
+
+use anyhow::{anyhow, Result};
+
+use osu_to_te::validate::{self, Severity};
+
+const USAGE: &str = "Usage: validate <map zip or song folder>";
+
+fn main() -> ExitCode {
+    match run(std::env::args().skip(1)) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Whether the map has no errors
+fn run(mut args: impl Iterator<Item = String>) -> Result<bool> {
+    let path = PathBuf::from(args.next().ok_or_else(|| anyhow!("No map given"))?);
+    let issues = if path.is_dir() {
+        validate::validate_dir(&path)?
+    } else {
+        validate::validate_zip(&std::fs::read(&path)?)?
+    };
+
+    if issues.is_empty() {
+        println!("No problems found in {}", path.display());
+    }
+    for issue in &issues {
+        println!("{issue}");
+    }
+    Ok(issues.iter().all(|i| i.severity != Severity::Error))
+}
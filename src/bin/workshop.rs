@@ -0,0 +1,41 @@
+//! Writes the Steam Workshop bundle of a Twin Edge map zip or installed song
+//! folder: the song folder, a preview image and a description with credits
+//!
+//! ```text
+//! workshop <map zip or song folder> <output folder>
+//! ```
+
+use std::{fs, path::PathBuf, process::ExitCode};
+
+use anyhow::{anyhow, Result};
+
+use osu_to_te::{te, workshop};
+
+const USAGE: &str = "Usage: workshop <map zip or song folder> <output folder>";
+
+fn main() -> ExitCode {
+    match run(std::env::args().skip(1)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let path = PathBuf::from(args.next().ok_or_else(|| anyhow!("No map given"))?);
+    let out_dir = PathBuf::from(
+        args.next()
+            .ok_or_else(|| anyhow!("No output folder given"))?,
+    );
+
+    let map = if path.is_dir() {
+        te::Map::read_dir(&path)?
+    } else {
+        te::Map::from_zip(&fs::read(&path)?)?
+    };
+    workshop::save_bundle(&map, &out_dir)?;
+    println!("Wrote the workshop bundle to {}", out_dir.display());
+    Ok(())
+}
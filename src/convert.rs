@@ -1,65 +1,140 @@
-use osuparse::HitObject;
-use std::fmt::Write as _;
-use te::TimingPoint;
+use std::sync::Arc;
 
-use crate::{osu, te};
+use anyhow::{anyhow, Result};
+use osuparse::{GameMode, HitObject};
+use serde_derive::{Deserialize, Serialize};
+use te::{Disc, Note, NoteType, TimingPoint};
 
-#[derive(Debug)]
+use crate::{
+    audio::{self, AudioFormat, Edits, Pcm, Transcoded},
+    error::Warning,
+    hitsound,
+    onset::Analysis,
+    osu,
+    overlap::{self, OverlapPolicy},
+    quantize, rating, reduce,
+    report::{Report, SkippedTimingPoint},
+    sanitize,
+    sync::{self, OffsetCheck},
+    te,
+    thumb::{self, ThumbMode},
+};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum SpinnerBehaviour {
     Ignore,
     Both,
     Current,
 }
 
-pub fn convert(
-    osu_map: &osu::Map,
-    slider_beat_limit: f32,
-    reverse_hitsound_mask: i32,
-    crop_thumb: bool,
-    spinner_behaviour: SpinnerBehaviour,
-) -> te::Map {
-    log::trace!(
-        "Converting with args: slider_beat_limit={}, reverse_hitsound_mask={:b}, crop_thumb={}, spinner_behaviour={:?}",
-        slider_beat_limit, reverse_hitsound_mask, crop_thumb, spinner_behaviour
-    );
-
-    let bpm = osu_map.find_most_used_bpm();
-
-    let thumb = if crop_thumb {
-        osu_map.thumb.clone().crop(
-            (osu_map.thumb.width() - osu_map.thumb.height()) / 2,
-            0,
-            osu_map.thumb.height(),
-            osu_map.thumb.height(),
-        )
-    } else {
-        osu_map.thumb.clone()
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Options {
+    /// Sliders shorter than this many beats become normal notes
+    pub slider_beat_limit: f32,
+    /// Hitsound flags that turn a note into a reverse note
+    pub reverse_hitsound_mask: i32,
+    /// How the background is made square
+    pub thumb_mode: ThumbMode,
+    /// Width and height of the thumbnail in pixels
+    pub thumb_size: u32,
+    pub spinner_behaviour: SpinnerBehaviour,
+    /// Beat divisors notes are snapped to, empty to keep the original times
+    pub snap_divisors: Vec<u32>,
+    /// Notes moved further than this many ms by snapping are reported
+    pub snap_tolerance: f64,
+    /// How objects overlapping on the same disc are resolved
+    pub overlap_policy: OverlapPolicy,
+    /// Adds reduced Easy and Normal charts as additional difficulties
+    pub generate_easier: bool,
+    /// Appends the star rating to the difficulty names
+    pub rating_in_name: bool,
+    /// Stamps the difficulty names, star ratings and mapper on the thumbnail,
+    /// unless it was generated
+    pub thumb_overlay: bool,
+    /// Format the audio is written in
+    pub audio_format: AudioFormat,
+    /// Ms added to every time in the song, to make up for audio latency
+    pub global_offset: f64,
+    /// Adds the map's AudioLeadIn as silence before the audio
+    pub apply_lead_in: bool,
+    /// Removes silence at the start of the audio
+    pub trim_silence: bool,
+    /// Playback speed the song is converted to, like DT at 1.5
+    pub rate: f64,
+    /// Keeps the pitch of the audio when changing the rate, like DT instead of NC
+    pub keep_pitch: bool,
+    /// Mixes the map's hitsounds into the audio
+    pub bake_hitsounds: bool,
+    /// Compares the notes with the onsets in the audio
+    pub offset_check: OffsetCheck,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            slider_beat_limit: 0.5,
+            reverse_hitsound_mask: 0b1110,
+            thumb_mode: ThumbMode::Centre,
+            thumb_size: 512,
+            spinner_behaviour: SpinnerBehaviour::Both,
+            snap_divisors: Vec::new(),
+            snap_tolerance: 3.,
+            overlap_policy: OverlapPolicy::Truncate,
+            generate_easier: false,
+            rating_in_name: false,
+            thumb_overlay: false,
+            audio_format: AudioFormat::Original,
+            global_offset: 0.,
+            apply_lead_in: false,
+            trim_silence: false,
+            rate: 1.,
+            keep_pitch: true,
+            bake_hitsounds: false,
+            offset_check: OffsetCheck::Off,
+        }
+    }
+}
+
+pub fn convert(osu_map: &osu::Map, options: &Options) -> Result<(te::Map, Report)> {
+    log::trace!("Converting with options: {options:?}");
+
+    let mut report = Report {
+        warnings: osu_map.warnings.clone(),
+        ..Default::default()
     };
-    let kiai = osu_map
-        .find_kiai()
-        .iter()
-        .map(|(s, e)| te::SpecialSection {
-            start_time: *s as _,
-            end_time: *e as _,
-            start_time_in_sec: *s as f64 / 1000.,
-            end_time_in_sec: *e as f64 / 1000.,
-        })
-        .collect();
-    let timing_points = osu_map
-        .data
-        .timing_points
-        .iter()
-        .skip(1) // skip first because timing point cannot be on start
-        .filter(|p| p.ms_per_beat > 0.)
-        .map(|p| TimingPoint {
-            time: p.offset as _,
-            bpm: (1000. / p.ms_per_beat as f64) * 60.,
-            time_in_sec: p.offset as f64 / 1000.,
-        })
-        .collect();
+    let timeline = osu_map.timeline()?;
+    report.main_bpm = osu_map.find_most_used_bpm(&timeline);
+    let (first_beat_offset, bpm, timing_points) =
+        convert_timing(&timeline, osu_map.end_time(&timeline), &mut report);
 
-    let mut left_notes = String::new();
-    let mut right_notes = String::new();
+    let metadata = &osu_map.data.metadata;
+    let background = match &osu_map.thumb {
+        Some(img) => Some(thumb::make_thumb(
+            img,
+            options.thumb_mode,
+            options.thumb_size,
+        )),
+        None => thumb::cover(
+            &osu_map.audio,
+            &osu_map.data.general.audio_filename,
+            options.thumb_mode,
+            options.thumb_size,
+        ),
+    };
+    // Generated thumbnails already show the difficulty, so they get no overlay
+    let overlay_thumb = options.thumb_overlay && background.is_some();
+    let mut thumb = background.unwrap_or_else(|| {
+        thumb::generated(
+            &metadata.title,
+            &metadata.artist,
+            &metadata.version,
+            options.thumb_size,
+        )
+    });
+
+    let mut left_notes = Vec::new();
+    let mut right_notes = Vec::new();
     let mut left = true;
     for o in &osu_map.data.hit_objects {
         // Switch sides when new combo
@@ -79,102 +154,557 @@ pub fn convert(
 
         // Convert and write object
         match o {
-            // a:b:c
-            // a time
-            // b type: 0: Normal, 1: Reverse, 2: Hold Start, 3: Hold End
-            // c angle?
             HitObject::HitCircle(o) => {
-                if o.hitsound & reverse_hitsound_mask == reverse_hitsound_mask
-                    && reverse_hitsound_mask != 0
-                {
-                    write!(current_side, "{}:1:0|", o.time).unwrap();
+                report.osu_objects.circles += 1;
+                let mask = options.reverse_hitsound_mask;
+                if o.hitsound & mask == mask && mask != 0 {
+                    report.reverse_notes.push(o.time as f64);
+                    current_side.push(Note::new(o.time as f64, NoteType::Reverse));
                 } else {
-                    write!(current_side, "{}:0:0|", o.time).unwrap();
+                    current_side.push(Note::new(o.time as f64, NoteType::Normal));
                 }
             }
             HitObject::Slider(o) => {
-                // Slider velocity
-                let slider_velocity = 1.
-                    / osu_map
-                        .data
-                        .timing_points
-                        .iter()
-                        .filter(|p| p.offset <= o.time as f32 + 0.0001 && p.ms_per_beat < 0.)
-                        .map(|p| -p.ms_per_beat / 100.)
-                        .last()
-                        .unwrap_or(1.);
-                // BPM
-                let beat_length = osu_map
-                    .data
-                    .timing_points
-                    .iter()
-                    .filter(|p| p.offset <= o.time as f32 + 0.0001 && p.ms_per_beat > 0.)
-                    .map(|p| p.ms_per_beat)
-                    .last()
-                    .unwrap_or(1.);
-
-                let slider_beat_time = o.pixel_length
-                    / (osu_map.data.difficulty.slider_multiplier * 100. * slider_velocity);
+                report.osu_objects.sliders += 1;
+                let beat_length = timeline.beat_length_at(o.time as f64);
+                let slider_beat_time = osu_map.slider_span_beats(&timeline, o);
 
                 // If slider is too short replace with normal note
-                if slider_beat_time > slider_beat_limit + 0.0001 {
-                    let slider_time = (slider_beat_time * beat_length) as i32;
-                    write!(
-                        current_side,
-                        "{}:2:0|{}:3:0|",
-                        o.time,
-                        o.time + slider_time * o.repeat
-                    )
-                    .unwrap();
+                if slider_beat_time > options.slider_beat_limit as f64 + 0.0001 {
+                    let slider_time = slider_beat_time * beat_length * o.repeat as f64;
+                    push_hold(current_side, o.time as f64, o.time as f64 + slider_time);
                 } else {
-                    write!(current_side, "{}:0:0|", o.time).unwrap();
+                    report.sliders_to_notes.push(o.time as f64);
+                    current_side.push(Note::new(o.time as f64, NoteType::Normal));
                 }
             }
-            HitObject::Spinner(o) => match spinner_behaviour {
-                SpinnerBehaviour::Ignore => {}
-                SpinnerBehaviour::Both => {
-                    write!(left_notes, "{}:2:0|{}:3:0|", o.time, o.end_time).unwrap();
-                    write!(right_notes, "{}:2:0|{}:3:0|", o.time, o.end_time).unwrap();
+            HitObject::Spinner(o) => {
+                report.osu_objects.spinners += 1;
+                match options.spinner_behaviour {
+                    SpinnerBehaviour::Ignore => report.ignored_spinners.push(o.time as f64),
+                    SpinnerBehaviour::Both => {
+                        push_hold(&mut left_notes, o.time as f64, o.end_time as f64);
+                        push_hold(&mut right_notes, o.time as f64, o.end_time as f64);
+                    }
+                    SpinnerBehaviour::Current => {
+                        push_hold(current_side, o.time as f64, o.end_time as f64);
+                    }
                 }
-                SpinnerBehaviour::Current => {
-                    write!(current_side, "{}:2:0|{}:3:0|", o.time, o.end_time).unwrap();
-                }
-            },
+            }
             HitObject::HoldNote(o) => {
-                write!(current_side, "{}:2:0|{}:3:0|", o.time, o.end_time).unwrap();
+                report.osu_objects.hold_notes += 1;
+                push_hold(current_side, o.time as f64, o.end_time as f64);
             }
         }
     }
-    left_notes.pop();
-    right_notes.pop();
-
-    let te_map = te::Map {
-        data: te::MapData {
-            mapper_name: osu_map.data.metadata.creator.clone(),
-            audio_file_name: osu_map.data.general.audio_filename.clone(),
-            thumbnail_file_name: "thumb.png".to_string(),
-            song_file_name: format!("{}.song", osu_map.data.metadata.title),
-            display_name: osu_map.data.metadata.title.clone(),
-            artist: osu_map.data.metadata.artist.clone(),
-            difficulty_name: osu_map.data.metadata.version.clone(),
-            preview_time_seconds: osu_map.data.general.preview_time as f64 / 1000.,
-            bpm,
-            difficulty_settings: te::DifficultySettings {
-                note_appear_time: 0.5,
-                rotation_speed: 125.,
-                health_drain_per_second: 5.,
-            },
-            first_beat_offset_in_ms: osu_map.data.timing_points.first().unwrap().offset as _,
-            timing_points,
-            right_disc_notes: right_notes,
-            left_disc_notes: left_notes,
-            song_events: Vec::new(),
-            special_sections: kiai,
-            breaks: Vec::new(),
-            additional_difficulties: Vec::new(),
+
+    if !options.snap_divisors.is_empty() {
+        for (disc, notes) in [
+            (Disc::Left, &mut left_notes),
+            (Disc::Right, &mut right_notes),
+        ] {
+            report.snapped.extend(quantize::quantize(
+                notes,
+                disc,
+                &timeline,
+                &options.snap_divisors,
+                options.snap_tolerance,
+            ));
+        }
+    }
+
+    report.overlaps = overlap::resolve(&mut left_notes, &mut right_notes, options.overlap_policy);
+
+    // Kiai sections without notes would only flash the screen
+    let kiai_notes: Vec<f64> = left_notes
+        .iter()
+        .chain(&right_notes)
+        .map(|n| n.time)
+        .filter(|t| timeline.kiai_at(*t))
+        .collect();
+    let kiai = timeline
+        .kiai_sections()
+        .iter()
+        .filter(|(s, e)| kiai_notes.iter().any(|t| (*s..*e).contains(t)))
+        .map(|(s, e)| te::SpecialSection {
+            start_time: *s,
+            end_time: *e,
+            start_time_in_sec: *s / 1000.,
+            end_time_in_sec: *e / 1000.,
+        })
+        .collect();
+
+    let audio = convert_audio(osu_map, &timeline, options, &mut report)?;
+
+    let mut data = te::MapData {
+        mapper_name: sanitize::text(&metadata.creator),
+        audio_file_name: sanitize::file_name(&audio.file_name),
+        thumbnail_file_name: "thumb.png".to_string(),
+        song_file_name: sanitize::file_name(&format!("{}.song", metadata.title)),
+        display_name: sanitize::text(&metadata.title),
+        artist: sanitize::text(&metadata.artist),
+        difficulty_name: sanitize::text(&metadata.version),
+        preview_time_seconds: osu_map.data.general.preview_time as f64 / 1000.,
+        bpm,
+        difficulty_settings: te::DifficultySettings {
+            note_appear_time: 0.5,
+            rotation_speed: 125.,
+            health_drain_per_second: 5.,
+        },
+        first_beat_offset_in_ms: first_beat_offset,
+        timing_points,
+        right_disc_notes: te::format_notes(&right_notes),
+        left_disc_notes: te::format_notes(&left_notes),
+        song_events: Vec::new(),
+        special_sections: kiai,
+        breaks: Vec::new(),
+        additional_difficulties: Vec::new(),
+    };
+    if options.generate_easier {
+        reduce::add_easier_difficulties(&mut data)?;
+    }
+    if options.rate != 1. {
+        data.change_rate(options.rate)?;
+        report.main_bpm *= options.rate;
+    }
+    data.shift(options.global_offset + audio.shift)?;
+    if options.offset_check != OffsetCheck::Off {
+        check_sync(&mut data, &audio.data, options.offset_check, &mut report)?;
+    }
+    // Counted from the final notes, so the peaks are at the times of the song
+    report.count_notes(&data.notes(Disc::Left)?, &data.notes(Disc::Right)?);
+    if overlay_thumb {
+        let ratings = rating::rate_all(&data)?;
+        let overlay = thumb::Overlay {
+            difficulties: ratings
+                .iter()
+                .map(|(name, rating)| (name.as_str(), rating.stars))
+                .collect(),
+            mapper: &data.mapper_name,
+        };
+        thumb = thumb::overlay(&thumb, &overlay);
+    }
+    if options.rating_in_name {
+        rating::append_to_names(&mut data)?;
+    }
+
+    Ok((
+        te::Map {
+            data,
+            audio: audio.data,
+            thumb,
+            source: Some(source(osu_map, options)?),
+        },
+        report,
+    ))
+}
+
+/// Credits and options recorded in the package
+fn source(osu_map: &osu::Map, options: &Options) -> Result<te::Source> {
+    let metadata = &osu_map.data.metadata;
+    let mode = match osu_map.data.general.game_mode {
+        GameMode::Osu => "osu",
+        GameMode::Taiko => "taiko",
+        GameMode::CTB => "fruits",
+        GameMode::Mania => "mania",
+    };
+    Ok(te::Source {
+        beatmap_set_id: metadata.beatmap_set_id,
+        beatmap_id: metadata.beatmap_id,
+        url: format!(
+            "https://osu.ppy.sh/beatmapsets/{}#{mode}/{}",
+            metadata.beatmap_set_id, metadata.beatmap_id
+        ),
+        artist: metadata.artist.clone(),
+        artist_unicode: metadata.artist_unicode.clone(),
+        title: metadata.title.clone(),
+        title_unicode: metadata.title_unicode.clone(),
+        song_source: metadata.source.clone(),
+        tags: metadata.tags.clone(),
+        mapper: metadata.creator.clone(),
+        difficulty: metadata.version.clone(),
+        osu_md5: osu_map.md5.clone(),
+        converter_version: env!("CARGO_PKG_VERSION").to_string(),
+        options: serde_json::to_value(options)?,
+    })
+}
+
+/// Compares the notes with the onsets in the final audio and moves them if
+/// `offset_check` is `Correct` and they seem off
+fn check_sync(
+    data: &mut te::MapData,
+    audio: &Arc<[u8]>,
+    offset_check: OffsetCheck,
+    report: &mut Report,
+) -> Result<()> {
+    // Missing audio is already reported by the download
+    if audio.is_empty() {
+        return Ok(());
+    }
+    let pcm = match Pcm::decode(audio, &data.audio_file_name) {
+        Ok(pcm) => pcm,
+        Err(e) => {
+            report
+                .warnings
+                .push(Warning::OffsetNotChecked(e.to_string()));
+            return Ok(());
+        }
+    };
+
+    report.sync = sync::check(data, &Analysis::new(&pcm));
+    if let Some(sync) = report.sync.clone().filter(|s| s.suspicious) {
+        if !sync.notes_shifted() {
+            // Moving everything would move the notes away from the audio,
+            // only the first beat offset needs fixing
+            report
+                .warnings
+                .push(Warning::FirstBeatOffGrid(sync.grid_error));
+        } else if offset_check == OffsetCheck::Correct {
+            data.shift(sync.note_error)?;
+            report.offset_correction = sync.note_error;
+        } else {
+            report
+                .warnings
+                .push(Warning::OffsetMismatch(sync.note_error));
+        }
+    }
+    Ok(())
+}
+
+/// Transcodes the audio, keeping the original if that isn't possible and the
+/// rate doesn't change
+fn convert_audio(
+    osu_map: &osu::Map,
+    timeline: &osu::Timeline,
+    options: &Options,
+    report: &mut Report,
+) -> Result<Transcoded> {
+    let file_name = &osu_map.data.general.audio_filename;
+    let original = Transcoded {
+        data: osu_map.audio.clone(),
+        file_name: file_name.clone(),
+        shift: 0.,
+    };
+    // Missing audio is already reported by the download
+    if osu_map.audio.is_empty() {
+        return Ok(original);
+    }
+
+    let edits = Edits {
+        rate: options.rate,
+        keep_pitch: options.keep_pitch,
+        lead_in: if options.apply_lead_in {
+            osu_map.data.general.audio_lead_in as f64
+        } else {
+            0.
+        },
+        trim_silence: options.trim_silence,
+        hitsounds: if options.bake_hitsounds {
+            hitsound::hitsounds(osu_map, timeline, &mut report.warnings)
+        } else {
+            Vec::new()
         },
-        audio: osu_map.audio.clone(),
-        thumb,
     };
-    te_map
+
+    // The original audio would be out of sync with the notes or miss the hitsounds
+    let format = if options.rate != 1. || options.bake_hitsounds {
+        AudioFormat::Wav
+    } else {
+        options.audio_format
+    };
+    if format == AudioFormat::Original && !edits.is_empty() {
+        report.warnings.push(Warning::AudioStartUnchanged);
+    }
+    match audio::transcode(&osu_map.audio, file_name, format, &edits) {
+        Ok(audio) => Ok(audio),
+        Err(e) if options.rate != 1. => Err(anyhow!("Could not change the audio rate: {e}")),
+        Err(e) => {
+            log::warn!("Could not transcode audio: {e}");
+            report
+                .warnings
+                .push(Warning::AudioNotTranscoded(e.to_string()));
+            Ok(original)
+        }
+    }
+}
+
+fn push_hold(notes: &mut Vec<Note>, start: f64, end: f64) {
+    notes.push(Note::new(start, NoteType::HoldStart));
+    notes.push(Note::new(end, NoteType::HoldEnd));
+}
+
+/// Converts the red lines of `timeline` up to `end` into the first beat
+/// offset, the base BPM and the timing points of a Twin Edge map. The base
+/// BPM is the one of the first red line, as a timing point can't be on the
+/// first beat. Red lines that are left out are added to `report`.
+fn convert_timing(
+    timeline: &osu::Timeline,
+    end: f64,
+    report: &mut Report,
+) -> (i64, f64, Vec<TimingPoint>) {
+    let lines = timeline.uninherited();
+    let skip = |report: &mut Report, lines: &[(f64, f64)], reason| {
+        report
+            .skipped_timing_points
+            .extend(lines.iter().map(|(time, _)| SkippedTimingPoint {
+                time: *time,
+                reason,
+            }))
+    };
+
+    // Start with the red line that is active at 0ms, earlier ones never apply
+    let mut first = lines.partition_point(|(o, _)| *o <= 0.).saturating_sub(1);
+    skip(report, &lines[..first], "overridden before 0ms");
+    let mut offset = lines[first].0;
+    // Move negative offsets forward by whole beats so the grid stays the same
+    if offset < 0. {
+        let beat_length = lines[first].1;
+        offset += (-offset / beat_length).ceil() * beat_length;
+        // The next red line is after 0ms, so it can take over directly
+        if lines
+            .get(first + 1)
+            .is_some_and(|(next, _)| offset >= *next)
+        {
+            skip(
+                report,
+                &lines[first..=first],
+                "overridden before its first beat",
+            );
+            first += 1;
+            offset = lines[first].0;
+        }
+    }
+
+    // Red lines after the last object don't affect anything
+    let after_end = lines.partition_point(|(o, _)| *o <= end).max(first + 1);
+    skip(report, &lines[after_end..], "after the last object");
+    let timing_points = lines[first + 1..after_end]
+        .iter()
+        .map(|(time, beat_length)| TimingPoint {
+            time: *time,
+            bpm: 60000. / beat_length,
+            time_in_sec: time / 1000.,
+        })
+        .collect();
+    (
+        offset.round() as i64,
+        60000. / lines[first].1,
+        timing_points,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use image::DynamicImage;
+    use osuparse::parse_beatmap;
+
+    use super::*;
+
+    fn convert_map(timing_points: &str, hit_objects: &str) -> Result<te::MapData> {
+        let options = Options {
+            reverse_hitsound_mask: 0,
+            thumb_mode: ThumbMode::Keep,
+            ..Default::default()
+        };
+        Ok(convert_with(timing_points, hit_objects, &options)?.0.data)
+    }
+
+    fn convert_with(
+        timing_points: &str,
+        hit_objects: &str,
+        options: &Options,
+    ) -> Result<(te::Map, Report)> {
+        let data = parse_beatmap(&format!(
+            "osu file format v14\n\n\
+            [General]\nAudioFilename: audio.mp3\n\n\
+            [Difficulty]\nSliderMultiplier:1\n\n\
+            [TimingPoints]\n{timing_points}\n\n\
+            [HitObjects]\n{hit_objects}\n"
+        ))
+        .unwrap();
+        let osu_map = osu::Map {
+            data,
+            audio: Vec::new().into(),
+            thumb: Some(DynamicImage::new_rgb8(2, 1)),
+            samples: HashMap::new(),
+            warnings: Vec::new(),
+            md5: String::new(),
+        };
+        convert(&osu_map, options)
+    }
+
+    fn timing_points(data: &te::MapData) -> Vec<(f64, f64)> {
+        data.timing_points.iter().map(|p| (p.time, p.bpm)).collect()
+    }
+
+    #[test]
+    fn options_round_trip() {
+        let options = Options {
+            slider_beat_limit: 1.,
+            thumb_mode: ThumbMode::Focus,
+            snap_divisors: vec![4],
+            ..Default::default()
+        };
+        let value = serde_json::to_value(&options).unwrap();
+        let read: Options = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&read).unwrap(), value);
+        // Options missing from older packages keep their defaults
+        let partial: Options = serde_json::from_str(r#"{"slider_beat_limit": 2.0}"#).unwrap();
+        assert_eq!(partial.slider_beat_limit, 2.);
+        assert_eq!(partial.thumb_size, 512);
+    }
+
+    #[test]
+    fn multiple_uninherited() {
+        let data = convert_map(
+            "0,500,4,2,1,50,1,0\n10000,250,4,2,1,50,1,0",
+            "256,192,1000,1,0\n256,192,30000,1,0",
+        )
+        .unwrap();
+        assert_eq!(data.bpm, 120.);
+        assert_eq!(data.first_beat_offset_in_ms, 0);
+        assert_eq!(timing_points(&data), vec![(10000., 240.)]);
+    }
+
+    #[test]
+    fn unsorted() {
+        let data = convert_map(
+            "10000,250,4,2,1,50,1,0\n0,500,4,2,1,50,1,0",
+            "256,192,1000,1,0\n256,192,30000,1,0",
+        )
+        .unwrap();
+        assert_eq!(data.bpm, 120.);
+        assert_eq!(data.first_beat_offset_in_ms, 0);
+        assert_eq!(timing_points(&data), vec![(10000., 240.)]);
+    }
+
+    #[test]
+    fn inherited_first() {
+        let data = convert_map(
+            "100,-50,4,2,1,50,0,0\n300,500,4,2,1,50,1,0",
+            "256,192,1000,1,0",
+        )
+        .unwrap();
+        assert_eq!(data.bpm, 120.);
+        assert_eq!(data.first_beat_offset_in_ms, 300);
+        assert!(data.timing_points.is_empty());
+    }
+
+    #[test]
+    fn negative_offset() {
+        let data = convert_map("-700,500,4,2,1,50,1,0", "256,192,1000,1,0").unwrap();
+        assert_eq!(data.bpm, 120.);
+        assert_eq!(data.first_beat_offset_in_ms, 300);
+        assert!(data.timing_points.is_empty());
+    }
+
+    #[test]
+    fn negative_offset_before_next_red_line() {
+        let data = convert_map(
+            "-700,500,4,2,1,50,1,0\n200,400,4,2,1,50,1,0",
+            "256,192,10000,1,0",
+        )
+        .unwrap();
+        assert_eq!(data.bpm, 150.);
+        assert_eq!(data.first_beat_offset_in_ms, 200);
+        assert!(data.timing_points.is_empty());
+    }
+
+    #[test]
+    fn points_after_last_note() {
+        let data = convert_map(
+            "0,500,4,2,1,50,1,0\n5000,250,4,2,1,50,1,0\n6000,200,4,2,1,50,1,0",
+            "256,192,1000,1,0\n256,192,2000,1,0",
+        )
+        .unwrap();
+        assert_eq!(data.bpm, 120.);
+        assert_eq!(data.first_beat_offset_in_ms, 0);
+        assert!(data.timing_points.is_empty());
+    }
+
+    #[test]
+    fn single_red_line() {
+        let data = convert_map("1000,500,4,2,1,50,1,0", "256,192,1000,1,0").unwrap();
+        assert_eq!(data.bpm, 120.);
+        assert_eq!(data.first_beat_offset_in_ms, 1000);
+        assert!(data.timing_points.is_empty());
+    }
+
+    #[test]
+    fn no_red_line() {
+        assert!(convert_map("0,-100,4,2,1,50,0,0", "256,192,1000,1,0").is_err());
+    }
+
+    #[test]
+    fn red_line_resets_slider_velocity() {
+        let data = convert_map(
+            "0,500,4,2,1,50,1,0\n0,-50,4,2,1,50,0,0\n1000,500,4,2,1,50,1,0",
+            "256,192,0,2,0,L|456:192,1,200\n256,192,1000,2,0,L|456:192,1,200",
+        )
+        .unwrap();
+        assert_eq!(data.left_disc_notes, "0:2:0|500:3:0|1000:2:0|2000:3:0");
+    }
+
+    #[test]
+    fn counts_notes_after_rate_change() {
+        let options = Options {
+            thumb_mode: ThumbMode::Keep,
+            rate: 1.5,
+            ..Default::default()
+        };
+        // 1200ms apart at 1x, 800ms at 1.5x
+        let (_, report) = convert_with(
+            "0,500,4,2,1,50,1,0",
+            "256,192,1000,1,0\n256,192,1300,1,0\n256,192,1600,1,0\n\
+            256,192,1900,1,0\n256,192,2200,1,0",
+            &options,
+        )
+        .unwrap();
+        assert_eq!(report.left_disc.total() + report.right_disc.total(), 5);
+        let peak = &report.density_peaks[0];
+        assert_eq!(peak.notes_per_second, 5);
+        assert!((peak.time - 1000. / 1.5).abs() < 1., "{}", peak.time);
+    }
+
+    #[test]
+    fn kiai_without_notes_is_dropped() {
+        // Kiai from 1000 to 2000 with a note, from 3000 to 4000 without
+        let data = convert_map(
+            "0,500,4,2,1,50,1,0\n1000,-100,4,2,1,50,0,1\n2000,-100,4,2,1,50,0,0\n\
+            3000,-100,4,2,1,50,0,1\n4000,-100,4,2,1,50,0,0",
+            "256,192,1500,1,0\n256,192,5000,1,0",
+        )
+        .unwrap();
+        let sections: Vec<(f64, f64)> = data
+            .special_sections
+            .iter()
+            .map(|s| (s.start_time, s.end_time))
+            .collect();
+        assert_eq!(sections, vec![(1000., 2000.)]);
+    }
+
+    #[test]
+    fn truncates_overlapping_slider() {
+        let data = convert_map(
+            "0,500,4,2,1,50,1,0",
+            "256,192,0,2,0,L|456:192,1,200\n256,192,800,1,0",
+        )
+        .unwrap();
+        assert_eq!(data.left_disc_notes, "0:2:0|770:3:0|800:0:0");
+    }
+
+    #[test]
+    fn shift_moves_first_beat_by_whole_beats() {
+        let mut data = convert_map(
+            "300,500,4,2,1,50,1,0\n2300,250,4,2,1,50,1,0",
+            "256,192,1000,1,0\n256,192,3000,1,0",
+        )
+        .unwrap();
+        data.shift(-400.).unwrap();
+        assert_eq!(data.left_disc_notes, "600:0:0|2600:0:0");
+        assert_eq!(timing_points(&data), vec![(1900., 240.)]);
+        assert_eq!(data.first_beat_offset_in_ms, 400);
+    }
 }
@@ -0,0 +1,98 @@
+use std::fmt::Display;
+
+use serde_derive::Serialize;
+
+/// Error that stops a conversion, by the stage it happened in
+#[derive(Debug)]
+pub enum Error {
+    /// Invalid url or options
+    Input(String),
+    Download(anyhow::Error),
+    Parse(anyhow::Error),
+    Convert(anyhow::Error),
+    Zip(anyhow::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Input(e) => write!(f, "{e}"),
+            Error::Download(e) => write!(f, "Could not download map: {e}"),
+            Error::Parse(e) => write!(f, "Could not read map: {e}"),
+            Error::Convert(e) => write!(f, "Could not convert map: {e}"),
+            Error::Zip(e) => write!(f, "Could not create zip: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Problem that doesn't stop a conversion but may make the result worse
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Warning {
+    NoBackground,
+    AudioMissing(String),
+    /// The audio couldn't be transcoded and was copied unchanged
+    AudioNotTranscoded(String),
+    /// A custom hitsound file used by the map isn't in the set
+    SampleMissing(String),
+    /// The notes seem to be this many ms early compared to the audio
+    OffsetMismatch(f64),
+    /// The notes line up with the audio, but the first beat seems to be this
+    /// many ms early compared to the beats of the audio
+    FirstBeatOffGrid(f64),
+    /// The audio couldn't be decoded to check the offset
+    OffsetNotChecked(String),
+    /// Lead-in or silence trimming was requested without re-encoding the audio
+    AudioStartUnchanged,
+    UnreadableDifficulty {
+        file: String,
+        error: String,
+    },
+    /// The .osu file differs from the one the package was converted from
+    SourceChanged,
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Warning::NoBackground => write!(f, "No background found, generated a thumbnail"),
+            Warning::SourceChanged => write!(
+                f,
+                "The osu! difficulty was updated since the map was converted"
+            ),
+            Warning::AudioMissing(file) => write!(f, "Audio file {file:?} is missing"),
+            Warning::SampleMissing(file) => {
+                write!(f, "Hitsound {file:?} is missing, used the default sounds")
+            }
+            Warning::OffsetMismatch(ms) => write!(
+                f,
+                "Notes seem {:.0}ms {} than the audio, try adding {ms:.0} to the offset",
+                ms.abs(),
+                if *ms > 0. { "earlier" } else { "later" }
+            ),
+            Warning::FirstBeatOffGrid(ms) => write!(
+                f,
+                "The first beat seems {:.0}ms {} than the audio, try adding {ms:.0} to first_beat_offset_in_ms",
+                ms.abs(),
+                if *ms > 0. { "earlier" } else { "later" }
+            ),
+            Warning::OffsetNotChecked(error) => {
+                write!(f, "Could not check the offset against the audio: {error}")
+            }
+            Warning::AudioStartUnchanged => write!(
+                f,
+                "Lead-in and silence trimming need the WAV audio format and were skipped"
+            ),
+            Warning::AudioNotTranscoded(error) => {
+                write!(
+                    f,
+                    "Kept the original audio because it couldn't be converted: {error}"
+                )
+            }
+            Warning::UnreadableDifficulty { file, error } => {
+                write!(f, "Skipped {file:?} because it couldn't be read: {error}")
+            }
+        }
+    }
+}
@@ -0,0 +1,348 @@
+use std::{collections::HashMap, f32::consts::TAU, rc::Rc, sync::Arc};
+
+use osuparse::{HitObject, HitObjectExtras};
+
+use crate::{
+    audio::{Hitsound, Pcm},
+    error::Warning,
+    osu::{self, Timeline},
+};
+
+/// Sample rate of the synthesized default samples
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum SampleSet {
+    Normal,
+    Soft,
+    Drum,
+}
+
+impl SampleSet {
+    /// Sample set of a timing point or object, `None` if it is inherited
+    fn from_index(index: i32) -> Option<SampleSet> {
+        match index {
+            1 => Some(SampleSet::Normal),
+            2 => Some(SampleSet::Soft),
+            3 => Some(SampleSet::Drum),
+            _ => None,
+        }
+    }
+
+    fn from_name(name: &str) -> SampleSet {
+        match name.to_lowercase().as_str() {
+            "soft" => SampleSet::Soft,
+            "drum" => SampleSet::Drum,
+            _ => SampleSet::Normal,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SampleSet::Normal => "normal",
+            SampleSet::Soft => "soft",
+            SampleSet::Drum => "drum",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Sound {
+    Normal,
+    Whistle,
+    Finish,
+    Clap,
+}
+
+impl Sound {
+    /// Sounds played on top of the normal sound by hitsound flag
+    const ADDITIONS: [(i32, Sound); 3] = [
+        (1 << 1, Sound::Whistle),
+        (1 << 2, Sound::Finish),
+        (1 << 3, Sound::Clap),
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Sound::Normal => "hitnormal",
+            Sound::Whistle => "hitwhistle",
+            Sound::Finish => "hitfinish",
+            Sound::Clap => "hitclap",
+        }
+    }
+}
+
+/// Point of an object that plays sounds, like a circle or slider end
+struct Edge<'a> {
+    time: f64,
+    hitsound: i32,
+    /// Sample sets set on a slider edge, 0 to use the object's
+    sets: (i32, i32),
+    extras: &'a HitObjectExtras,
+}
+
+/// Decoded samples of the set and synthesized defaults
+struct Samples<'a> {
+    files: &'a HashMap<String, Arc<[u8]>>,
+    cache: HashMap<String, Option<Rc<Pcm>>>,
+    defaults: HashMap<(SampleSet, Sound), Rc<Pcm>>,
+}
+
+impl Samples<'_> {
+    /// Decodes the file called `name` in the set, without extension if
+    /// `any_extension` is set
+    fn file(&mut self, name: &str, any_extension: bool) -> Option<Rc<Pcm>> {
+        let name = name.to_lowercase();
+        let files = self.files;
+        self.cache
+            .entry(name.clone())
+            .or_insert_with(|| {
+                let candidates = if any_extension {
+                    ["wav", "ogg", "mp3"]
+                        .iter()
+                        .map(|ext| format!("{name}.{ext}"))
+                        .collect()
+                } else {
+                    vec![name]
+                };
+                let (file, data) = candidates
+                    .iter()
+                    .find_map(|c| files.get(c).map(|data| (c, data)))?;
+                match Pcm::decode(data, file) {
+                    Ok(pcm) => Some(Rc::new(pcm)),
+                    Err(e) => {
+                        log::warn!("Could not decode hitsound {file:?}: {e}");
+                        None
+                    }
+                }
+            })
+            .clone()
+    }
+
+    fn default(&mut self, set: SampleSet, sound: Sound) -> Rc<Pcm> {
+        self.defaults
+            .entry((set, sound))
+            .or_insert_with(|| Rc::new(synthesize(set, sound)))
+            .clone()
+    }
+}
+
+/// Every sound osu! plays for the objects of `osu_map`. Custom samples of the
+/// set are used where the map asks for them, synthesized ones otherwise.
+pub fn hitsounds(
+    osu_map: &osu::Map,
+    timeline: &Timeline,
+    warnings: &mut Vec<Warning>,
+) -> Vec<Hitsound> {
+    let map_set = SampleSet::from_name(&osu_map.data.general.sample_set);
+    let mut samples = Samples {
+        files: &osu_map.samples,
+        cache: HashMap::new(),
+        defaults: HashMap::new(),
+    };
+
+    let mut hitsounds = Vec::new();
+    for edge in edges(osu_map, timeline) {
+        let point = timeline.samples_at(edge.time);
+        let volume = if edge.extras.sample_volume > 0 {
+            edge.extras.sample_volume
+        } else {
+            point.volume
+        } as f32
+            / 100.;
+
+        // Keysounds replace all other sounds
+        let custom_file = &edge.extras.filename;
+        if !custom_file.is_empty() {
+            if let Some(sample) = samples.file(custom_file, false) {
+                hitsounds.push(Hitsound {
+                    time: edge.time,
+                    volume,
+                    sample,
+                });
+                continue;
+            }
+            let warning = Warning::SampleMissing(custom_file.clone());
+            if !warnings.contains(&warning) {
+                warnings.push(warning);
+            }
+        }
+
+        let normal_set = [edge.sets.0, edge.extras.sample_set, point.sample_set]
+            .into_iter()
+            .find_map(SampleSet::from_index)
+            .unwrap_or(map_set);
+        let addition_set = [edge.sets.1, edge.extras.addition_set]
+            .into_iter()
+            .find_map(SampleSet::from_index)
+            .unwrap_or(normal_set);
+        let index = if edge.extras.custom_index > 0 {
+            edge.extras.custom_index
+        } else {
+            point.sample_index
+        };
+
+        let sounds = std::iter::once((normal_set, Sound::Normal)).chain(
+            Sound::ADDITIONS
+                .iter()
+                .filter(|(flag, _)| edge.hitsound & flag != 0)
+                .map(|(_, sound)| (addition_set, *sound)),
+        );
+        for (set, sound) in sounds {
+            // Index 0 means the default skin samples, 1 has no number in the file name
+            let custom = (index > 0)
+                .then(|| {
+                    let number = if index == 1 {
+                        String::new()
+                    } else {
+                        index.to_string()
+                    };
+                    samples.file(&format!("{}-{}{number}", set.name(), sound.name()), true)
+                })
+                .flatten();
+            hitsounds.push(Hitsound {
+                time: edge.time,
+                volume,
+                sample: custom.unwrap_or_else(|| samples.default(set, sound)),
+            });
+        }
+    }
+    hitsounds
+}
+
+/// Points of every object that play sounds
+fn edges<'a>(osu_map: &'a osu::Map, timeline: &Timeline) -> Vec<Edge<'a>> {
+    let mut edges = Vec::new();
+    for o in &osu_map.data.hit_objects {
+        match o {
+            HitObject::HitCircle(o) => edges.push(Edge {
+                time: o.time as f64,
+                hitsound: o.hitsound,
+                sets: (0, 0),
+                extras: &o.extras,
+            }),
+            HitObject::Slider(o) => {
+                let span =
+                    osu_map.slider_span_beats(timeline, o) * timeline.beat_length_at(o.time as f64);
+                for i in 0..=o.repeat.max(1) as usize {
+                    edges.push(Edge {
+                        time: o.time as f64 + span * i as f64,
+                        hitsound: o.edge_hitsounds.get(i).copied().unwrap_or(o.hitsound),
+                        sets: o.edge_additions.get(i).copied().unwrap_or((0, 0)),
+                        extras: &o.extras,
+                    });
+                }
+            }
+            HitObject::Spinner(o) => edges.push(Edge {
+                time: o.end_time as f64,
+                hitsound: o.hitsound,
+                sets: (0, 0),
+                extras: &o.extras,
+            }),
+            HitObject::HoldNote(o) => edges.push(Edge {
+                time: o.time as f64,
+                hitsound: o.hitsound,
+                sets: (0, 0),
+                extras: &o.extras,
+            }),
+        }
+    }
+    edges
+}
+
+/// Simple stand-in for the default osu! samples, which can't be shipped
+fn synthesize(set: SampleSet, sound: Sound) -> Pcm {
+    let rate = DEFAULT_SAMPLE_RATE as f32;
+    // (length, decay time in s, tone frequency, noise amount)
+    let (length, decay, frequency, noise) = match sound {
+        Sound::Normal => (
+            0.08,
+            0.015,
+            match set {
+                SampleSet::Normal => 300.,
+                SampleSet::Soft => 200.,
+                SampleSet::Drum => 90.,
+            },
+            match set {
+                SampleSet::Normal => 0.5,
+                SampleSet::Soft => 0.2,
+                SampleSet::Drum => 0.3,
+            },
+        ),
+        Sound::Whistle => (
+            0.2,
+            0.06,
+            match set {
+                SampleSet::Normal => 1800.,
+                SampleSet::Soft => 1400.,
+                SampleSet::Drum => 2200.,
+            },
+            0.,
+        ),
+        Sound::Finish => (0.6, 0.2, 0., 1.),
+        Sound::Clap => (0.1, 0.02, 0., 1.),
+    };
+    let volume = if set == SampleSet::Soft { 0.4 } else { 0.6 };
+
+    // Deterministic noise so conversions are reproducible
+    let mut seed: u32 = 0x9e37_79b9;
+    let samples = (0..(length * rate) as usize)
+        .map(|i| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let t = i as f32 / rate;
+            let random = seed as f32 / u32::MAX as f32 * 2. - 1.;
+            let tone = (TAU * frequency * t).sin();
+            (tone * (1. - noise) + random * noise) * (-t / decay).exp() * volume
+        })
+        .collect();
+
+    Pcm {
+        sample_rate: DEFAULT_SAMPLE_RATE,
+        channels: 1,
+        samples,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use osuparse::parse_beatmap;
+
+    use super::*;
+
+    #[test]
+    fn uses_custom_samples_and_timing_point_volume() {
+        let data = parse_beatmap(
+            "osu file format v14\n\n\
+            [General]\nAudioFilename: audio.mp3\nSampleSet: Normal\n\n\
+            [Difficulty]\nSliderMultiplier:1\n\n\
+            [TimingPoints]\n0,500,4,2,2,50,1,0\n\n\
+            [HitObjects]\n256,192,1000,1,8\n",
+        )
+        .unwrap();
+        let clap = Pcm {
+            sample_rate: 8000,
+            channels: 1,
+            samples: vec![0.5; 80],
+        };
+        let osu_map = osu::Map {
+            data,
+            audio: Vec::new().into(),
+            thumb: None,
+            samples: HashMap::from([(
+                "soft-hitclap2.wav".to_string(),
+                clap.to_wav().unwrap().into(),
+            )]),
+            warnings: Vec::new(),
+            md5: String::new(),
+        };
+
+        let hitsounds = hitsounds(&osu_map, &osu_map.timeline().unwrap(), &mut Vec::new());
+        assert_eq!(hitsounds.len(), 2);
+        assert!(hitsounds.iter().all(|h| h.time == 1000. && h.volume == 0.5));
+        // The normal sound has no custom sample and is synthesized
+        assert_eq!(hitsounds[0].sample.sample_rate, DEFAULT_SAMPLE_RATE);
+        assert_eq!(hitsounds[1].sample.samples.len(), 80);
+    }
+}
@@ -1,15 +1,46 @@
+mod audio;
+mod autochart;
 mod convert;
+mod error;
+mod hitsound;
+mod onset;
 mod osu;
-mod te;
+mod overlap;
+mod quantize;
+pub mod rating;
+pub mod reconvert;
+mod reduce;
+pub mod report;
+mod sanitize;
+mod sync;
+pub mod te;
+mod thumb;
+pub mod validate;
+pub mod workshop;
 
 use std::str::FromStr;
 
 use anyhow::anyhow;
 use wasm_bindgen::{prelude::*, JsCast};
 use wasm_bindgen_futures::JsFuture;
-use web_sys::{HtmlElement, HtmlInputElement, HtmlSelectElement, Request, RequestInit};
+use web_sys::{
+    Blob, Document, HtmlElement, HtmlInputElement, HtmlSelectElement, Request, RequestInit, Url,
+};
 
-use crate::convert::SpinnerBehaviour;
+use crate::{
+    audio::AudioFormat,
+    autochart::AutoOptions,
+    convert::{Options, SpinnerBehaviour},
+    error::Error,
+    overlap::OverlapPolicy,
+    report::Report,
+    sync::OffsetCheck,
+    te::{Compression, ZipOptions},
+    thumb::ThumbMode,
+};
+
+/// Ms a download url stays valid after the download is started
+const REVOKE_DELAY_MS: i32 = 60000;
 
 #[wasm_bindgen]
 pub fn run() {
@@ -21,7 +52,12 @@ pub fn run() {
 #[wasm_bindgen]
 pub async fn convert_url() {
     hide_error();
+    if let Err(e) = try_convert_url().await {
+        show_error(&e.to_string());
+    }
+}
 
+async fn try_convert_url() -> Result<(), Error> {
     // Read inputs
     let doc = web_sys::window()
         .and_then(|w| w.document())
@@ -31,20 +67,124 @@ pub async fn convert_url() {
         .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
         .expect("Error getting url")
         .value();
-    let url = url::Url::from_str(&url_str);
 
+    let save_report: bool = doc
+        .get_element_by_id("save_report")
+        .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
+        .expect("Error getting report toggle")
+        .checked();
+    let workshop_bundle: bool = doc
+        .get_element_by_id("workshop_bundle")
+        .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
+        .expect("Error getting workshop bundle toggle")
+        .checked();
+
+    let options = read_options(&doc)?;
+    let zip_options = read_zip_options(&doc)?;
+
+    // Start conversion
+    let url = url::Url::from_str(&url_str).map_err(|_| Error::Input("Invalid url".to_string()))?;
+    log::trace!("Converting url {url}");
+    show_message(&format!("Converting url {url}"));
+    log_discord(&format!("Converting url {url}")).await;
+
+    let url_error = || {
+        Error::Input(
+            "Only urls in the form 'https://osu.ppy.sh/beatmapsets/{set_id}#osu/{map_id}' are supported"
+                .to_string(),
+        )
+    };
+    if url.domain() != Some("osu.ppy.sh") || !url.path().starts_with("/beatmapsets/") {
+        return Err(url_error());
+    }
+
+    let set_id: u64 = url
+        .path_segments()
+        .and_then(|mut s| s.next_back())
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(url_error)?;
+    log::trace!("Beatmap set {set_id}");
+
+    let map_id: u64 = url
+        .fragment()
+        .and_then(|f| f.split('/').next_back())
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| {
+            Error::Input("Converting multiple difficulties not supported yet".to_string())
+        })?;
+    log::trace!("Difficulty {map_id}");
+
+    show_message("Downloading...");
+    let map_file = osu::download(set_id).await.map_err(Error::Download)?;
+    let diff = map_file
+        .into_iter()
+        .find(|m| m.data.metadata.beatmap_id == map_id as i32)
+        .ok_or_else(|| Error::Parse(anyhow!("Difficulty {map_id} is not in the beatmap set")))?;
+
+    show_message("Converting...");
+    let (te_map, report) = convert::convert(&diff, &options).map_err(Error::Convert)?;
+    save_converted(&te_map, &report, zip_options, save_report, workshop_bundle)?;
+    log_discord(&format!("Converted {map_id}")).await;
+    Ok(())
+}
+
+/// Form elements of every conversion option in the form
+const OPTION_INPUTS: [(&str, &[&str]); 18] = [
+    ("slider_beat_limit", &["slider_beat_limit"]),
+    (
+        "reverse_hitsound_mask",
+        &["mask_normal", "mask_whistle", "mask_finish", "mask_clap"],
+    ),
+    ("thumb_mode", &["thumb_mode"]),
+    ("thumb_size", &["thumb_size"]),
+    ("spinner_behaviour", &["spinner_behaviour"]),
+    ("snap_divisors", &["snap_divisors"]),
+    ("overlap_policy", &["overlap_policy"]),
+    ("generate_easier", &["generate_easier"]),
+    ("rating_in_name", &["rating_in_name"]),
+    ("thumb_overlay", &["thumb_overlay"]),
+    ("audio_format", &["audio_format"]),
+    ("global_offset", &["global_offset"]),
+    ("apply_lead_in", &["apply_lead_in"]),
+    ("trim_silence", &["trim_silence"]),
+    ("rate", &["rate"]),
+    ("keep_pitch", &["keep_pitch"]),
+    ("bake_hitsounds", &["bake_hitsounds"]),
+    ("offset_check", &["offset_check"]),
+];
+
+/// Conversion options set in the form
+fn read_options(doc: &Document) -> Result<Options, Error> {
     let slider_beat_limit: f32 = doc
         .get_element_by_id("slider_beat_limit")
         .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
         .expect("Error getting slider limit")
         .value()
         .parse()
-        .unwrap();
-    let crop_thumb: bool = doc
-        .get_element_by_id("crop_thumb")
+        .map_err(|_| Error::Input("Slider limit has to be a number".to_string()))?;
+    let thumb_mode: ThumbMode = match doc
+        .get_element_by_id("thumb_mode")
+        .and_then(|e| e.dyn_into::<HtmlSelectElement>().ok())
+        .expect("Error getting thumbnail mode")
+        .value()
+        .as_str()
+    {
+        "keep" => ThumbMode::Keep,
+        "focus" => ThumbMode::Focus,
+        "blur" => ThumbMode::BlurFill,
+        _ => ThumbMode::Centre,
+    };
+    let thumb_size: u32 = doc
+        .get_element_by_id("thumb_size")
         .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
-        .expect("Error getting crop toggle")
-        .checked();
+        .expect("Error getting thumbnail size")
+        .value()
+        .parse()
+        .ok()
+        .filter(|s| (16..=4096).contains(s))
+        .ok_or_else(|| {
+            Error::Input("Thumbnail size has to be a number from 16 to 4096".to_string())
+        })?;
     let spinner_behaviour: SpinnerBehaviour = match doc
         .get_element_by_id("spinner_behaviour")
         .and_then(|e| e.dyn_into::<HtmlSelectElement>().ok())
@@ -57,6 +197,24 @@ pub async fn convert_url() {
         _ => SpinnerBehaviour::Both,
     };
 
+    let generate_easier: bool = doc
+        .get_element_by_id("generate_easier")
+        .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
+        .expect("Error getting easier difficulties toggle")
+        .checked();
+
+    let rating_in_name: bool = doc
+        .get_element_by_id("rating_in_name")
+        .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
+        .expect("Error getting rating toggle")
+        .checked();
+
+    let thumb_overlay: bool = doc
+        .get_element_by_id("thumb_overlay")
+        .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
+        .expect("Error getting thumbnail overlay toggle")
+        .checked();
+
     let mask_normal: bool = doc
         .get_element_by_id("mask_normal")
         .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
@@ -78,6 +236,90 @@ pub async fn convert_url() {
         .expect("Error getting clap mask")
         .checked();
 
+    let overlap_policy: OverlapPolicy = match doc
+        .get_element_by_id("overlap_policy")
+        .and_then(|e| e.dyn_into::<HtmlSelectElement>().ok())
+        .expect("Error getting overlap policy")
+        .value()
+        .as_str()
+    {
+        "keep" => OverlapPolicy::Keep,
+        "move" => OverlapPolicy::MoveToOtherDisc,
+        "drop" => OverlapPolicy::Drop,
+        _ => OverlapPolicy::Truncate,
+    };
+
+    let audio_format: AudioFormat = match doc
+        .get_element_by_id("audio_format")
+        .and_then(|e| e.dyn_into::<HtmlSelectElement>().ok())
+        .expect("Error getting audio format")
+        .value()
+        .as_str()
+    {
+        "wav" => AudioFormat::Wav,
+        _ => AudioFormat::Original,
+    };
+
+    let global_offset: f64 = doc
+        .get_element_by_id("global_offset")
+        .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
+        .expect("Error getting global offset")
+        .value()
+        .parse()
+        .map_err(|_| Error::Input("Offset has to be a number".to_string()))?;
+    let apply_lead_in: bool = doc
+        .get_element_by_id("apply_lead_in")
+        .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
+        .expect("Error getting lead-in toggle")
+        .checked();
+    let trim_silence: bool = doc
+        .get_element_by_id("trim_silence")
+        .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
+        .expect("Error getting silence toggle")
+        .checked();
+
+    let rate: f64 = doc
+        .get_element_by_id("rate")
+        .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
+        .expect("Error getting rate")
+        .value()
+        .parse()
+        .ok()
+        .filter(|r| (0.5..=2.).contains(r))
+        .ok_or_else(|| Error::Input("Rate has to be a number from 0.5 to 2".to_string()))?;
+    let keep_pitch: bool = doc
+        .get_element_by_id("keep_pitch")
+        .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
+        .expect("Error getting pitch toggle")
+        .checked();
+
+    let bake_hitsounds: bool = doc
+        .get_element_by_id("bake_hitsounds")
+        .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
+        .expect("Error getting hitsound toggle")
+        .checked();
+
+    let offset_check: OffsetCheck = match doc
+        .get_element_by_id("offset_check")
+        .and_then(|e| e.dyn_into::<HtmlSelectElement>().ok())
+        .expect("Error getting offset check")
+        .value()
+        .as_str()
+    {
+        "report" => OffsetCheck::Report,
+        "correct" => OffsetCheck::Correct,
+        _ => OffsetCheck::Off,
+    };
+
+    let snap_divisors: Vec<u32> = doc
+        .get_element_by_id("snap_divisors")
+        .and_then(|e| e.dyn_into::<HtmlSelectElement>().ok())
+        .expect("Error getting snap divisors")
+        .value()
+        .split(',')
+        .filter_map(|d| d.parse().ok())
+        .collect();
+
     let mut reverse_hitsound_mask = 0;
     if mask_normal {
         reverse_hitsound_mask |= 1 << 0;
@@ -92,81 +334,262 @@ pub async fn convert_url() {
         reverse_hitsound_mask |= 1 << 3;
     }
 
-    // Start conversion
-    if url.is_err() {
-        panic_with("Invalid url").await;
-    }
-    let url = url.unwrap();
-    log::trace!("Converting url {url}");
-    show_message(&format!("Converting url {url}"));
-    log_discord(&format!("Converting url {url}")).await.unwrap();
+    Ok(Options {
+        slider_beat_limit,
+        reverse_hitsound_mask,
+        thumb_mode,
+        thumb_size,
+        spinner_behaviour,
+        snap_divisors,
+        overlap_policy,
+        generate_easier,
+        rating_in_name,
+        thumb_overlay,
+        audio_format,
+        global_offset,
+        apply_lead_in,
+        trim_silence,
+        rate,
+        keep_pitch,
+        bake_hitsounds,
+        offset_check,
+        ..Default::default()
+    })
+}
 
-    if url.domain().is_none()
-        || url.domain().unwrap_or("") != "osu.ppy.sh"
-        || !url.path().starts_with("/beatmapsets/")
-    {
-        panic_with("Only urls in the form 'https://osu.ppy.sh/beatmapsets/{set_id}#osu/{map_id}' are supported").await;
-    }
+/// Options with an element the user changed, marked by main.js
+fn changed_options(doc: &Document) -> Vec<&'static str> {
+    OPTION_INPUTS
+        .iter()
+        .filter(|(_, ids)| {
+            ids.iter().any(|id| {
+                doc.get_element_by_id(id)
+                    .is_some_and(|e| e.has_attribute("data-changed"))
+            })
+        })
+        .map(|(key, _)| *key)
+        .collect()
+}
 
-    let set_id: u64 = url
-        .path_segments()
-        .unwrap()
-        .last()
-        .unwrap()
+/// Zip options set in the form
+fn read_zip_options(doc: &Document) -> Result<ZipOptions, Error> {
+    let zip_level: i32 = doc
+        .get_element_by_id("zip_level")
+        .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
+        .expect("Error getting compression level")
+        .value()
         .parse()
-        .unwrap();
-    log::trace!("Beatmap set {set_id}");
+        .ok()
+        .filter(|l| (0..=9).contains(l))
+        .ok_or_else(|| {
+            Error::Input("Compression level has to be a number from 0 to 9".to_string())
+        })?;
+    Ok(ZipOptions {
+        compression: match doc
+            .get_element_by_id("zip_compression")
+            .and_then(|e| e.dyn_into::<HtmlSelectElement>().ok())
+            .expect("Error getting zip compression")
+            .value()
+            .as_str()
+        {
+            "deflate" => Compression::Deflated(zip_level),
+            _ => Compression::Stored,
+        },
+    })
+}
 
-    let map_id: Option<u64> = url
-        .fragment()
-        .and_then(|f| f.split('/').last())
-        .and_then(|s| s.parse().ok());
-    if let Some(map_id) = map_id {
-        log::trace!("Difficulty {map_id}");
-
-        show_message("Downloading...");
-        let map_file = osu::download(set_id).await;
-        if let Ok(map_file) = map_file {
-            let diff = map_file
-                .into_iter()
-                .find(|m| m.data.metadata.beatmap_id == map_id as i32)
-                .unwrap();
-            show_message("Converting...");
-            let te_map = convert::convert(
-                &diff,
-                slider_beat_limit,
-                reverse_hitsound_mask,
-                crop_thumb,
-                spinner_behaviour,
-            );
-            show_message("Generating zip...");
-            let zip = te_map.as_zip().unwrap();
-            show_message("Saving...");
-            download_file(
-                &format!(
-                    "{} {} ({}).zip",
-                    te_map.data.artist, te_map.data.display_name, te_map.data.mapper_name
-                ),
-                &zip,
-            );
-            show_message("Done");
-            hide_error();
-            log_discord(&format!("Converted {map_id}")).await.unwrap();
-        } else {
-            log::trace!("{map_file:?}");
-            panic_with("Could not download map file").await;
+/// Rates, zips and downloads a converted map and shows the result
+fn save_converted(
+    te_map: &te::Map,
+    report: &Report,
+    zip_options: ZipOptions,
+    save_report: bool,
+    workshop_bundle: bool,
+) -> Result<(), Error> {
+    log::trace!("{report:?}");
+    let rating = rating::rate_map(&te_map.data).map_err(Error::Convert)?;
+    log::trace!("Rated {rating}");
+
+    show_message("Generating zip...");
+    let zip = te_map.as_zip(zip_options).map_err(Error::Zip)?;
+    show_message("Saving...");
+    let name = sanitize::file_name(&format!(
+        "{} {} ({})",
+        te_map.data.artist, te_map.data.display_name, te_map.data.mapper_name
+    ));
+    download_file(&format!("{name}.zip"), &zip);
+    if save_report {
+        let report_json =
+            serde_json::to_string_pretty(&report).map_err(|e| Error::Zip(e.into()))?;
+        download_file(&format!("{name} report.json"), report_json.as_bytes());
+    }
+    if workshop_bundle {
+        let bundle = workshop::bundle_zip(te_map, zip_options).map_err(Error::Zip)?;
+        download_file(&format!("{name} workshop.zip"), &bundle);
+    }
+
+    let mut message = format!("Done, rated {rating}. {report}");
+    for w in &report.warnings {
+        message += &format!("\nWarning: {w}");
+    }
+    let audio_length = audio::length(&te_map.audio, &te_map.data.audio_file_name).ok();
+    for issue in validate::validate(&te_map.data, audio_length) {
+        if issue.severity == validate::Severity::Error {
+            message += &format!("\n{issue}");
         }
-    } else {
-        panic_with("Converting multiple difficulties not supported yet").await;
     }
+    show_message(&message);
+    Ok(())
 }
 
 #[wasm_bindgen]
 pub fn convert_file() {
-    todo!()
+    hide_error();
+    show_error("Converting files isn't supported yet");
 }
 
-async fn panic_with(err: &str) {
+#[wasm_bindgen]
+pub async fn autochart_file() {
+    hide_error();
+    if let Err(e) = try_autochart_file().await {
+        show_error(&e.to_string());
+    }
+}
+
+async fn try_autochart_file() -> Result<(), Error> {
+    let doc = web_sys::window()
+        .and_then(|w| w.document())
+        .expect("Error getting document");
+    let notes_per_second: f64 = doc
+        .get_element_by_id("autochart_density")
+        .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
+        .expect("Error getting note density")
+        .value()
+        .parse()
+        .ok()
+        .filter(|d| *d > 0.)
+        .ok_or_else(|| Error::Input("Note density has to be a positive number".to_string()))?;
+    let zip_options = read_zip_options(&doc)?;
+    let (name, audio) = read_file("audio_file")
+        .await?
+        .ok_or_else(|| Error::Input("Select an audio file first".to_string()))?;
+
+    show_message("Charting...");
+    let options = AutoOptions {
+        notes_per_second,
+        ..Default::default()
+    };
+    let te_map = autochart::autochart(audio.into(), &name, &options).map_err(Error::Convert)?;
+    let rating = rating::rate_map(&te_map.data).map_err(Error::Convert)?;
+
+    show_message("Generating zip...");
+    let zip = te_map.as_zip(zip_options).map_err(Error::Zip)?;
+    download_file(
+        &sanitize::file_name(&format!("{}.zip", te_map.data.display_name)),
+        &zip,
+    );
+    show_message(&format!("Done, {} BPM, rated {rating}", te_map.data.bpm));
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub async fn reconvert_file() {
+    hide_error();
+    if let Err(e) = try_reconvert_file().await {
+        show_error(&e.to_string());
+    }
+}
+
+/// Converts a Twin Edge zip again from its osu! map with the options in the
+/// form
+async fn try_reconvert_file() -> Result<(), Error> {
+    let doc = web_sys::window()
+        .and_then(|w| w.document())
+        .expect("Error getting document");
+    let save_report: bool = doc
+        .get_element_by_id("save_report")
+        .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
+        .expect("Error getting report toggle")
+        .checked();
+    let workshop_bundle: bool = doc
+        .get_element_by_id("workshop_bundle")
+        .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
+        .expect("Error getting workshop bundle toggle")
+        .checked();
+    let options = read_options(&doc)?;
+    let zip_options = read_zip_options(&doc)?;
+    // Only what was changed in the form, the rest comes from the package
+    let changes = reconvert::fields(&options, &changed_options(&doc))
+        .map_err(|e| Error::Input(e.to_string()))?;
+
+    let (_, zip) = read_file("te_file")
+        .await?
+        .ok_or_else(|| Error::Input("Select a Twin Edge zip file first".to_string()))?;
+    let package = te::Map::from_zip(&zip).map_err(Error::Parse)?;
+    let source = reconvert::source(&package).map_err(Error::Parse)?;
+    log_discord(&format!("Reconverting {}", source.url)).await;
+
+    show_message("Downloading...");
+    let maps = osu::download(source.beatmap_set_id as u64)
+        .await
+        .map_err(Error::Download)?;
+    let diff = reconvert::find_difficulty(source, maps).map_err(Error::Parse)?;
+
+    show_message("Converting...");
+    let (te_map, report) =
+        reconvert::reconvert(&package, &diff, &changes).map_err(Error::Convert)?;
+    save_converted(&te_map, &report, zip_options, save_report, workshop_bundle)?;
+    log_discord(&format!("Reconverted {}", source.beatmap_id)).await;
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub async fn validate_file() {
+    hide_error();
+    if let Err(e) = try_validate_file().await {
+        show_error(&e.to_string());
+    }
+}
+
+async fn try_validate_file() -> Result<(), Error> {
+    let (_, zip) = read_file("te_file")
+        .await?
+        .ok_or_else(|| Error::Input("Select a Twin Edge zip file first".to_string()))?;
+    let issues = validate::validate_zip(&zip).map_err(Error::Parse)?;
+    if issues.is_empty() {
+        show_message("No problems found");
+    } else {
+        let issues: Vec<String> = issues.iter().map(|i| i.to_string()).collect();
+        show_message(&issues.join("\n"));
+    }
+    Ok(())
+}
+
+/// Reads the name and content of the file selected in the file input with the id `id`
+async fn read_file(id: &str) -> Result<Option<(String, Vec<u8>)>, Error> {
+    let file = web_sys::window()
+        .and_then(|w| w.document())
+        .and_then(|d| d.get_element_by_id(id))
+        .and_then(|e| e.dyn_into::<HtmlInputElement>().ok())
+        .expect("Error getting file input")
+        .files()
+        .and_then(|f| f.get(0));
+    let file = match file {
+        Some(file) => file,
+        None => return Ok(None),
+    };
+
+    let buffer = JsFuture::from(file.array_buffer())
+        .await
+        .map_err(|e| Error::Input(format!("Could not read file: {e:?}")))?;
+    Ok(Some((
+        file.name(),
+        js_sys::Uint8Array::new(&buffer).to_vec(),
+    )))
+}
+
+fn show_error(err: &str) {
+    log::error!("{}", err);
     web_sys::window()
         .and_then(|w| w.document())
         .and_then(|d| d.get_element_by_id("error-container"))
@@ -176,7 +599,6 @@ async fn panic_with(err: &str) {
                 .unwrap_or_else(|_| panic!("Error displaying error: {err}"));
         })
         .unwrap_or_else(|| panic!("Error displaying error: {err}"));
-    panic!("{}", err);
 }
 
 fn hide_error() {
@@ -204,24 +626,47 @@ fn show_message(text: &str) {
         .unwrap_or_else(|| panic!("Error displaying text: {text}"));
 }
 
+/// Saves `content` through a blob, which unlike a data url doesn't need
+/// another base64 copy of the whole file
 fn download_file(name: &str, content: &[u8]) {
-    web_sys::window()
-        .and_then(|w| w.document())
+    let window = web_sys::window().expect("Error saving file");
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(content));
+    let url = Blob::new_with_u8_array_sequence(&parts)
+        .and_then(|blob| Url::create_object_url_with_blob(&blob))
+        .expect("Error saving file");
+    window
+        .document()
         .and_then(|d| d.create_element("a").ok())
         .and_then(|e| {
             e.set_attribute("download", name).ok()?;
-            e.set_attribute(
-                "href",
-                &("data:application/octet-stream;base64,".to_owned() + &base64::encode(content)),
-            )
-            .ok()?;
+            e.set_attribute("href", &url).ok()?;
             e.dyn_into::<HtmlElement>().ok()?.click();
             Some(())
         })
-        .expect("Error saving file")
+        .expect("Error saving file");
+    // Firefox and Safari may still be reading the blob after the click
+    // returns, revoking the url right away can cancel the download
+    let revoke = Closure::once_into_js(move || {
+        if let Err(e) = Url::revoke_object_url(&url) {
+            log::warn!("Could not revoke download url: {e:?}");
+        }
+    });
+    window
+        .set_timeout_with_callback_and_timeout_and_arguments_0(
+            revoke.unchecked_ref(),
+            REVOKE_DELAY_MS,
+        )
+        .expect("Error saving file");
+}
+
+/// Logs to discord, failures are only written to the console
+async fn log_discord(text: &str) {
+    if let Err(e) = try_log_discord(text).await {
+        log::warn!("Could not log to discord: {e}");
+    }
 }
 
-async fn log_discord(text: &str) -> anyhow::Result<()> {
+async fn try_log_discord(text: &str) -> anyhow::Result<()> {
     let mut opts = RequestInit::new();
     opts.method("POST");
     let headers = js_sys::Map::new();
@@ -0,0 +1,291 @@
+use realfft::RealFftPlanner;
+
+use crate::audio::Pcm;
+
+/// Samples per analysis frame
+const FRAME_SIZE: usize = 1024;
+/// Samples between the starts of analysis frames
+const HOP_SIZE: usize = 512;
+/// Onsets closer than this many ms to a stronger one are ignored
+const MIN_ONSET_GAP: f64 = 60.;
+/// Onsets further than this many ms from a beat don't count as fitting it
+const FIT_WINDOW: f64 = 20.;
+/// Number of the strongest onsets used to fit the beat grid
+const FIT_ONSETS: usize = 1000;
+
+/// A point where a new sound starts in the audio
+#[derive(Debug, Clone, PartialEq)]
+pub struct Onset {
+    pub time: f64,
+    /// Spectral flux relative to the strongest onset
+    pub strength: f32,
+    /// Spectral centroid in Hz, higher for brighter sounds
+    pub centroid: f32,
+}
+
+/// Spectral flux onset analysis of a song
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    /// Ms between analysis frames
+    pub frame_ms: f64,
+    /// Loudness of every frame
+    pub rms: Vec<f32>,
+    pub onsets: Vec<Onset>,
+    /// Length of the audio in ms
+    pub length: f64,
+}
+
+impl Analysis {
+    pub fn new(pcm: &Pcm) -> Analysis {
+        let channels = pcm.channels.max(1) as usize;
+        let mono: Vec<f32> = pcm
+            .samples
+            .chunks(channels)
+            .map(|c| c.iter().sum::<f32>() / channels as f32)
+            .collect();
+        let frame_ms = HOP_SIZE as f64 * 1000. / pcm.sample_rate as f64;
+        let frame_count = if mono.len() < FRAME_SIZE {
+            0
+        } else {
+            (mono.len() - FRAME_SIZE) / HOP_SIZE + 1
+        };
+
+        let fft = RealFftPlanner::<f32>::new().plan_fft_forward(FRAME_SIZE);
+        let window: Vec<f32> = (0..FRAME_SIZE)
+            .map(|i| 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / FRAME_SIZE as f32).cos())
+            .collect();
+        let mut input = fft.make_input_vec();
+        let mut spectrum = fft.make_output_vec();
+        let mut previous = vec![0.; spectrum.len()];
+
+        let mut flux = Vec::with_capacity(frame_count);
+        let mut centroids = Vec::with_capacity(frame_count);
+        let mut rms = Vec::with_capacity(frame_count);
+        for i in 0..frame_count {
+            let frame = &mono[i * HOP_SIZE..i * HOP_SIZE + FRAME_SIZE];
+            for ((x, s), w) in input.iter_mut().zip(frame).zip(&window) {
+                *x = s * w;
+            }
+            fft.process(&mut input, &mut spectrum)
+                .expect("FFT buffers have the planned size");
+
+            let magnitudes: Vec<f32> = spectrum.iter().map(|c| c.norm()).collect();
+            let mut frame_flux = 0.;
+            for (m, p) in magnitudes.iter().zip(&mut previous) {
+                // Log compression so quiet sounds count too
+                let m = (1. + 100. * m).ln();
+                frame_flux += (m - *p).max(0.);
+                *p = m;
+            }
+            flux.push(frame_flux);
+
+            let total: f32 = magnitudes.iter().sum();
+            let hz_per_bin = pcm.sample_rate as f32 / FRAME_SIZE as f32;
+            centroids.push(if total > 0. {
+                magnitudes
+                    .iter()
+                    .enumerate()
+                    .map(|(k, m)| k as f32 * hz_per_bin * m)
+                    .sum::<f32>()
+                    / total
+            } else {
+                0.
+            });
+            rms.push((frame.iter().map(|s| s * s).sum::<f32>() / FRAME_SIZE as f32).sqrt());
+        }
+        // The first frame compares against silence
+        if let Some(f) = flux.first_mut() {
+            *f = 0.;
+        }
+
+        let max = flux.iter().copied().fold(0., f32::max);
+        if max > 0. {
+            flux.iter_mut().for_each(|f| *f /= max);
+        }
+
+        // Peaks clearly above the surrounding average
+        let mut onsets: Vec<Onset> = Vec::new();
+        for i in 0..flux.len() {
+            let near = &flux[i.saturating_sub(3)..(i + 4).min(flux.len())];
+            let around = &flux[i.saturating_sub(16)..(i + 17).min(flux.len())];
+            let mean = around.iter().sum::<f32>() / around.len() as f32;
+            if flux[i] < near.iter().copied().fold(0., f32::max) || flux[i] < mean + 0.05 {
+                continue;
+            }
+
+            // Time of the frame centre, moved towards the stronger neighbour
+            let (l, r) = (flux[i.saturating_sub(1)], *flux.get(i + 1).unwrap_or(&0.));
+            let curve = l - 2. * flux[i] + r;
+            let shift = if curve < 0. {
+                0.5 * (l - r) / curve
+            } else {
+                0.
+            };
+            let frame = i as f64 + shift as f64;
+            let time = (frame * HOP_SIZE as f64 + (FRAME_SIZE / 2) as f64) * 1000.
+                / pcm.sample_rate as f64;
+            let onset = Onset {
+                time,
+                strength: flux[i],
+                centroid: centroids[i],
+            };
+            match onsets.last_mut() {
+                Some(last) if time - last.time < MIN_ONSET_GAP => {
+                    if onset.strength > last.strength {
+                        *last = onset;
+                    }
+                }
+                _ => onsets.push(onset),
+            }
+        }
+
+        Analysis {
+            frame_ms,
+            rms,
+            onsets,
+            length: mono.len() as f64 * 1000. / pcm.sample_rate as f64,
+        }
+    }
+
+    /// Loudness at `time`
+    pub fn rms_at(&self, time: f64) -> f32 {
+        let i = (time / self.frame_ms).max(0.) as usize;
+        self.rms.get(i).copied().unwrap_or(0.)
+    }
+
+    /// The strongest onsets, used to fit beat grids
+    fn fit_onsets(&self) -> Vec<&Onset> {
+        let mut onsets: Vec<&Onset> = self.onsets.iter().collect();
+        onsets.sort_by(|a, b| b.strength.total_cmp(&a.strength));
+        onsets.truncate(FIT_ONSETS);
+        onsets
+    }
+
+    /// How well the onsets line up with beats of `beat_length` starting at
+    /// `offset`, weighted by strength
+    fn grid_fit(onsets: &[&Onset], beat_length: f64, offset: f64) -> f64 {
+        onsets
+            .iter()
+            .map(|o| {
+                let phase = (o.time - offset).rem_euclid(beat_length);
+                let distance = phase.min(beat_length - phase);
+                o.strength as f64 * (1. - distance / FIT_WINDOW).max(0.)
+            })
+            .sum()
+    }
+
+    /// Offset in [0, beat_length) in steps of `step` ms that fits the onsets best
+    fn best_offset(onsets: &[&Onset], beat_length: f64, step: f64) -> (f64, f64) {
+        (0..(beat_length / step) as usize)
+            .map(|i| {
+                let offset = i as f64 * step;
+                (offset, Self::grid_fit(onsets, beat_length, offset))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap_or((0., 0.))
+    }
+
+    /// Offset in ms of the beat grid with `beat_length` that fits the onsets best
+    pub fn grid_offset(&self, beat_length: f64) -> f64 {
+        Self::best_offset(&self.fit_onsets(), beat_length, 1.).0
+    }
+
+    /// Estimates (BPM, offset in ms of the first beat) from the onsets, `None`
+    /// if the audio has no clear beat
+    pub fn estimate_tempo(&self) -> Option<(f64, f64)> {
+        let onsets = self.fit_onsets();
+        if onsets.len() < 4 {
+            return None;
+        }
+
+        // Autocorrelation of an onset train for beat lengths from 60 to 200 BPM
+        let frames = (self.length / self.frame_ms) as usize + 1;
+        let mut train = vec![0f32; frames];
+        for o in &onsets {
+            if let Some(t) = train.get_mut((o.time / self.frame_ms) as usize) {
+                *t += o.strength;
+            }
+        }
+        let min_lag = (300. / self.frame_ms) as usize;
+        let max_lag = ((1000. / self.frame_ms) as usize).min(frames.saturating_sub(1));
+        let correlation = |lag: usize| -> f64 {
+            // Neighbouring frames count too, onsets rarely hit the exact frame
+            (0..frames - lag)
+                .map(|i| {
+                    let next = train[i + lag - 1..(i + lag + 2).min(frames)]
+                        .iter()
+                        .sum::<f32>();
+                    (train[i] * next) as f64
+                })
+                .sum()
+        };
+        let (lag, _) = (min_lag..=max_lag)
+            .map(|lag| {
+                // Prefer tempos near 120 BPM over their halves and doubles
+                let bpm = 60000. / (lag as f64 * self.frame_ms);
+                let prior = (-0.5 * ((bpm / 120.).log2() / 0.6).powi(2)).exp();
+                (lag, correlation(lag) * prior)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))?;
+        let coarse = 60000. / (lag as f64 * self.frame_ms);
+
+        // Refine between the neighbouring lags by fitting the beat grid
+        let slowest = 60000. / ((lag + 1) as f64 * self.frame_ms);
+        let steps = ((coarse / slowest - 1.) / 0.0005).ceil() as i32;
+        let (bpm, _) = (-steps..=steps)
+            .map(|i| coarse * (1. + i as f64 * 0.0005))
+            .map(|bpm| (bpm, Self::best_offset(&onsets, 60000. / bpm, 4.).1))
+            .max_by(|a, b| a.1.total_cmp(&b.1))?;
+        // Songs are usually made with whole BPMs
+        let rounded = bpm.round();
+        let bpm = if Self::best_offset(&onsets, 60000. / rounded, 4.).1
+            >= Self::best_offset(&onsets, 60000. / bpm, 4.).1 * 0.98
+        {
+            rounded
+        } else {
+            (bpm * 100.).round() / 100.
+        };
+
+        Some((bpm, Self::best_offset(&onsets, 60000. / bpm, 1.).0))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// Clicks every beat with a quieter one halfway, starting at `offset`
+    pub fn click_track(bpm: f64, offset: f64, seconds: f64) -> Pcm {
+        let sample_rate = 22050;
+        let mut samples = vec![0.; (seconds * sample_rate as f64) as usize];
+        let beat = 60. / bpm * sample_rate as f64;
+        let mut seed: u32 = 1;
+        let mut start = offset / 1000. * sample_rate as f64;
+        let mut strong = true;
+        while (start as usize) < samples.len() {
+            for i in 0..2000.min(samples.len() - start as usize) {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let noise = (seed >> 16) as f32 / 32768. - 1.;
+                let volume = if strong { 0.8 } else { 0.3 };
+                samples[start as usize + i] += noise * volume * (-(i as f32) / 300.).exp();
+            }
+            start += beat / 2.;
+            strong = !strong;
+        }
+        Pcm {
+            sample_rate,
+            channels: 1,
+            samples,
+        }
+    }
+
+    #[test]
+    fn finds_tempo_and_offset() {
+        let analysis = Analysis::new(&click_track(128., 230., 20.));
+        assert!(analysis.onsets.len() >= 80);
+
+        let (bpm, offset) = analysis.estimate_tempo().unwrap();
+        assert_eq!(bpm, 128.);
+        assert!((offset - 230.).abs() < 15., "offset {offset}");
+    }
+}
@@ -1,4 +1,8 @@
-use std::io::{Cursor, Read};
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read},
+    sync::Arc,
+};
 
 use anyhow::{anyhow, Result};
 use image::DynamicImage;
@@ -8,7 +12,8 @@ use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::JsFuture;
 use web_sys::{Blob, Request, RequestInit, Response};
 
-use super::Map;
+use super::{background, is_image, is_sample, storyboard_background, Map};
+use crate::error::Warning;
 
 pub async fn download(set_id: u64) -> Result<Vec<Map>> {
     let mut opts = RequestInit::new();
@@ -47,8 +52,12 @@ pub async fn download(set_id: u64) -> Result<Vec<Map>> {
 
     let mut archive = zip::ZipArchive::new(Cursor::new(&data))?;
 
+    // Difficulties with the background named in their events
     let mut maps = Vec::new();
-    let mut thumb = None;
+    let mut warnings = Vec::new();
+    let mut samples = HashMap::new();
+    let mut images = HashMap::new();
+    let mut storyboards = Vec::new();
 
     for i in 0..archive.len() {
         let mut file = archive.by_index(i)?;
@@ -57,32 +66,89 @@ pub async fn download(set_id: u64) -> Result<Vec<Map>> {
 
             let mut file_data = Vec::with_capacity(file.size() as _);
             file.read_to_end(&mut file_data)?;
-            let osu_data = std::str::from_utf8(&file_data)?;
-
-            let data = parse_beatmap(osu_data).map_err(|e| anyhow!(e))?;
-
-            maps.push(Map {
-                data,
-                audio: Vec::new(),
-                thumb: DynamicImage::new_rgb8(1, 1),
-            })
-        } else {
+            let data = std::str::from_utf8(&file_data)
+                .map_err(|e| anyhow!(e))
+                .and_then(|d| parse_beatmap(d).map_err(|e| anyhow!(e)));
+
+            match data {
+                Ok(data) => {
+                    let text = String::from_utf8_lossy(&file_data);
+                    let background = background(&text).or_else(|| storyboard_background(&text));
+                    maps.push((
+                        Map {
+                            data,
+                            audio: Arc::from([]),
+                            thumb: None,
+                            samples: HashMap::new(),
+                            warnings: Vec::new(),
+                            md5: format!("{:x}", md5::compute(&file_data)),
+                        },
+                        background,
+                    ))
+                }
+                Err(e) => {
+                    log::warn!("Could not parse {}: {e}", file.name());
+                    warnings.push(Warning::UnreadableDifficulty {
+                        file: file.name().to_string(),
+                        error: e.to_string(),
+                    })
+                }
+            }
+        } else if is_sample(file.name()) {
+            let mut sample_data = Vec::with_capacity(file.size() as _);
+            file.read_to_end(&mut sample_data)?;
+            samples.insert(file.name().to_lowercase(), Arc::from(sample_data));
+        } else if is_image(file.name()) {
             let mut image_data = Vec::with_capacity(file.size() as _);
             file.read_to_end(&mut image_data)?;
-            if let Ok(img) = image::load_from_memory(&image_data) {
-                thumb = Some(img);
-            }
+            images.insert(file.name().to_lowercase(), image_data);
+        } else if file.name().to_lowercase().ends_with(".osb") {
+            let mut storyboard = Vec::with_capacity(file.size() as _);
+            file.read_to_end(&mut storyboard)?;
+            storyboards.push(String::from_utf8_lossy(&storyboard).into_owned());
         }
     }
 
+    // Images are only decoded once even if several difficulties use them
+    let mut decoded: HashMap<String, Option<DynamicImage>> = HashMap::new();
+    let mut maps: Vec<Map> = maps
+        .into_iter()
+        .map(|(mut map, background)| {
+            let background =
+                background.or_else(|| storyboards.iter().find_map(|s| storyboard_background(s)));
+            map.thumb = background.and_then(|name| {
+                decoded
+                    .entry(name.clone())
+                    .or_insert_with(|| {
+                        let img = images.get(&name)?;
+                        image::load_from_memory(img)
+                            .map_err(|e| log::warn!("Could not decode background {name}: {e}"))
+                            .ok()
+                    })
+                    .clone()
+            });
+            if map.thumb.is_none() {
+                map.warnings.push(Warning::NoBackground);
+            }
+            map
+        })
+        .collect();
+
     for map in &mut maps {
-        if let Some(thumb) = &thumb {
-            map.thumb = thumb.to_owned();
+        map.warnings.splice(0..0, warnings.iter().cloned());
+        map.samples = samples.clone();
+        map.samples
+            .remove(&map.data.general.audio_filename.to_lowercase());
+        match archive.by_name(&map.data.general.audio_filename) {
+            Ok(mut audio) => {
+                let mut audio_data = Vec::with_capacity(audio.size() as _);
+                audio.read_to_end(&mut audio_data)?;
+                map.audio = audio_data.into();
+            }
+            Err(_) => map.warnings.push(Warning::AudioMissing(
+                map.data.general.audio_filename.clone(),
+            )),
         }
-        let mut audio = archive.by_name(&map.data.general.audio_filename)?;
-        let mut audio_data = Vec::with_capacity(audio.size() as _);
-        audio.read_to_end(&mut audio_data)?;
-        map.audio = audio_data;
     }
 
     log::trace!("Imported {} difficulties", maps.len());
@@ -0,0 +1,79 @@
+//! Reads the [Events] section of .osu and .osb files, which osuparse skips
+
+/// Whether `file_name` is an image osu! can show
+pub fn is_image(file_name: &str) -> bool {
+    let name = file_name.to_lowercase();
+    [".jpg", ".jpeg", ".png", ".bmp"]
+        .iter()
+        .any(|ext| name.ends_with(ext))
+}
+
+/// Path of a file in the beatmap folder as written in an event, normalized
+/// to lowercase with forward slashes like the names in a set archive
+pub fn normalize_path(path: &str) -> String {
+    path.trim()
+        .trim_matches('"')
+        .replace('\\', "/")
+        .trim_start_matches("./")
+        .to_lowercase()
+}
+
+/// Lines of the [Events] section, without comments
+fn event_lines(text: &str) -> impl Iterator<Item = &str> {
+    let mut in_events = false;
+    text.lines().filter(move |line| {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') && trimmed.ends_with(']') {
+            in_events = trimmed == "[Events]";
+            return false;
+        }
+        in_events && !trimmed.is_empty() && !trimmed.starts_with("//")
+    })
+}
+
+/// Background image of a difficulty, from a line like `0,0,"bg.jpg",0,0`
+pub fn background(osu: &str) -> Option<String> {
+    event_lines(osu).find_map(|line| {
+        let mut parts = line.split(',');
+        let kind = parts.next()?.trim();
+        if kind != "0" && kind != "Background" {
+            return None;
+        }
+        let file = normalize_path(parts.nth(1)?);
+        is_image(&file).then_some(file)
+    })
+}
+
+/// First sprite on the background layer of the storyboard in `text`, from a
+/// line like `Sprite,Background,Centre,"sb/bg.jpg",320,240`
+pub fn storyboard_background(text: &str) -> Option<String> {
+    event_lines(text).find_map(|line| {
+        let parts: Vec<&str> = line.split(',').collect();
+        let is_background_sprite = parts.len() >= 4
+            && parts[0] == "Sprite"
+            && matches!(parts[1].trim(), "Background" | "0");
+        let file = normalize_path(parts.get(3)?);
+        (is_background_sprite && is_image(&file)).then_some(file)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_background_lines() {
+        let osu = "[General]\nAudioFilename: a.mp3\n\n\
+            [Events]\n//Background and Video events\n\
+            Video,0,\"intro.avi\"\n0,0,\"Backgrounds\\BG.JPG\",0,0\n\
+            //Storyboard Layer 0 (Background)\n\
+            Sprite,Background,Centre,\"sb\\sky.png\",320,240\n F,0,0,1000,0,1\n\n\
+            [TimingPoints]\n0,500,4,2,1,50,1,0\n";
+        assert_eq!(background(osu).as_deref(), Some("backgrounds/bg.jpg"));
+        assert_eq!(storyboard_background(osu).as_deref(), Some("sb/sky.png"));
+        assert_eq!(
+            background("[Events]\nSprite,Foreground,Centre,\"a.png\",0,0\n"),
+            None
+        );
+    }
+}
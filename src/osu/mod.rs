@@ -1,17 +1,59 @@
 mod download;
+mod events;
+mod timeline;
 
 pub use download::*;
+pub use events::*;
+pub use timeline::*;
 
-use std::{collections::BTreeMap, fmt::Debug, fs, path::Path};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use anyhow::{anyhow, Result};
 use image::DynamicImage;
-use osuparse::{parse_beatmap, Beatmap};
+use osuparse::{parse_beatmap, Beatmap, HitObject, Slider};
+
+use crate::error::Warning;
 
 pub struct Map {
     pub data: Beatmap,
-    pub audio: Vec<u8>,
-    pub thumb: DynamicImage,
+    /// Shared so decoding and copying it into the converted map is cheap
+    pub audio: Arc<[u8]>,
+    /// Background image, if the set has one
+    pub thumb: Option<DynamicImage>,
+    /// Audio files of the set other than the song, by lowercase file name
+    pub samples: HashMap<String, Arc<[u8]>>,
+    pub warnings: Vec<Warning>,
+    /// MD5 of the .osu file in hex
+    pub md5: String,
+}
+
+/// Whether `file_name` can be a hitsound sample
+pub fn is_sample(file_name: &str) -> bool {
+    let name = file_name.to_lowercase();
+    [".wav", ".ogg", ".mp3"]
+        .iter()
+        .any(|ext| name.ends_with(ext))
+}
+
+/// Path of the file at `path` relative to `dir`, as normalized by
+/// `normalize_path`. Paths in events are case insensitive like on Windows,
+/// so every folder on the way is matched ignoring case.
+fn find_file(dir: &Path, path: &str) -> Option<PathBuf> {
+    path.split('/')
+        .filter(|c| !c.is_empty())
+        .try_fold(dir.to_path_buf(), |dir, component| {
+            dir.read_dir()
+                .ok()?
+                .filter_map(|f| f.ok())
+                .find(|f| f.file_name().to_string_lossy().to_lowercase() == component)
+                .map(|f| f.path())
+        })
 }
 
 impl Debug for Map {
@@ -19,81 +61,164 @@ impl Debug for Map {
         f.debug_struct("Map")
             .field("audio", &self.audio)
             .field("thumb", &self.thumb)
+            .field("samples", &self.samples.keys())
+            .field("warnings", &self.warnings)
             .finish()
     }
 }
 
 impl Map {
     pub fn read<P: AsRef<Path>>(path: P) -> Result<Map> {
-        let data = parse_beatmap(&fs::read_to_string(&path)?).map_err(|e| anyhow!(e))?;
-
-        let audio = fs::read(
-            path.as_ref()
-                .parent()
-                .unwrap()
-                .join(&data.general.audio_filename),
-        )?;
-
-        let thumb = path
-            .as_ref()
-            .parent()
-            .unwrap()
-            .read_dir()
-            .unwrap()
-            .find_map(|f| image::open(f.unwrap().path()).ok())
-            .ok_or_else(|| anyhow!("Could not find thumb file"))?;
-
-        Ok(Map { data, audio, thumb })
+        let text = fs::read_to_string(&path)?;
+        let data = parse_beatmap(&text).map_err(|e| anyhow!(e))?;
+
+        let dir = path.as_ref().parent().unwrap();
+        let audio_filename = &data.general.audio_filename;
+        let audio_path = find_file(dir, &normalize_path(audio_filename))
+            .unwrap_or_else(|| dir.join(audio_filename));
+        let audio = fs::read(audio_path)?.into();
+
+        let mut samples = HashMap::new();
+        for f in dir.read_dir()? {
+            let f = f?;
+            let name = f.file_name().to_string_lossy().into_owned();
+            if is_sample(&name) && !name.eq_ignore_ascii_case(audio_filename) {
+                samples.insert(name.to_lowercase(), fs::read(f.path())?.into());
+            }
+        }
+
+        let background = background(&text)
+            .or_else(|| storyboard_background(&text))
+            .or_else(|| {
+                dir.read_dir()
+                    .ok()?
+                    .filter_map(|f| f.ok())
+                    .filter(|f| {
+                        f.file_name()
+                            .to_string_lossy()
+                            .to_lowercase()
+                            .ends_with(".osb")
+                    })
+                    .find_map(|f| storyboard_background(&fs::read_to_string(f.path()).ok()?))
+            });
+        let mut warnings = Vec::new();
+        let thumb = background.and_then(|background| {
+            let background_path =
+                find_file(dir, &background).unwrap_or_else(|| dir.join(&background));
+            image::open(background_path)
+                .map_err(|e| log::warn!("Could not open background {background}: {e}"))
+                .ok()
+        });
+        if thumb.is_none() {
+            warnings.push(Warning::NoBackground);
+        }
+
+        Ok(Map {
+            data,
+            audio,
+            thumb,
+            samples,
+            warnings,
+            md5: format!("{:x}", md5::compute(&text)),
+        })
     }
 
-    pub fn find_most_used_bpm(&self) -> f64 {
-        let timing_points = &self.data.timing_points;
+    /// BPM that covers the most time between the first uninherited point
+    /// and the end of the last object
+    pub fn find_most_used_bpm(&self, timeline: &Timeline) -> f64 {
+        let end = self.end_time(timeline);
+        let lines = timeline.uninherited();
 
+        // Keyed by BPM in thousandths so equal BPMs add up
         let mut bpms = BTreeMap::new();
+        for (i, (offset, beat_length)) in lines.iter().enumerate() {
+            let section_end = lines.get(i + 1).map_or(end, |(next, _)| next.min(end));
+            let bpm_key = (60000. / beat_length * 1000.).round() as i64;
+            *bpms.entry(bpm_key).or_insert(0.) += (section_end - offset).max(0.);
+        }
 
-        let mut last_bpm =
-            ((1000. / timing_points.first().unwrap().ms_per_beat as f64) * 60. * 1000.).round()
-                as i64;
-        let mut last_time = timing_points.first().unwrap().offset as u64;
-        for p in timing_points {
-            if p.ms_per_beat < 0. {
-                continue;
-            }
+        // Fall back to the first red line if no section has any length
+        let first_key = (60000. / lines[0].1 * 1000.).round() as i64;
+        let (key, _) =
+            bpms.into_iter().fold(
+                (first_key, 0.),
+                |best, (k, v)| if v > best.1 { (k, v) } else { best },
+            );
+        key as f64 / 1000.
+    }
 
-            let bpm_key = ((1000. / p.ms_per_beat as f64) * 60. * 1000.).round() as i64;
+    /// Length of a single slider span in beats
+    pub fn slider_span_beats(&self, timeline: &Timeline, slider: &Slider) -> f64 {
+        slider.pixel_length as f64
+            / (self.data.difficulty.slider_multiplier as f64
+                * 100.
+                * timeline.sv_at(slider.time as f64))
+    }
 
-            bpms.entry(bpm_key).or_insert(0);
-            bpms.insert(
-                last_bpm,
-                bpms.get(&last_bpm).unwrap() + p.offset as u64 - last_time,
-            );
-            last_bpm = bpm_key;
-            last_time = p.offset as u64;
-        }
+    /// Time in ms at which the last object ends
+    pub fn end_time(&self, timeline: &Timeline) -> f64 {
+        self.data
+            .hit_objects
+            .iter()
+            .map(|o| match o {
+                HitObject::HitCircle(o) => o.time as f64,
+                HitObject::Slider(o) => {
+                    o.time as f64
+                        + self.slider_span_beats(timeline, o)
+                            * timeline.beat_length_at(o.time as f64)
+                            * o.repeat as f64
+                }
+                HitObject::Spinner(o) => o.end_time as f64,
+                HitObject::HoldNote(o) => o.end_time as f64,
+            })
+            .fold(0., f64::max)
+    }
 
-        let max = bpms.iter().max_by_key(|(_, v)| *v).unwrap();
-        *max.0 as f64 / 1000.
+    pub fn timeline(&self) -> Result<Timeline> {
+        Timeline::new(&self.data.timing_points)
     }
+}
 
-    pub fn find_kiai(&self) -> Vec<(f32, f32)> {
-        let timing_points = &self.data.timing_points;
-        let mut kiai = Vec::new();
-
-        let mut kiai_on = false;
-        let mut kiai_start = 0.;
-        for p in timing_points {
-            if p.kiai_mode && !kiai_on {
-                kiai_on = true;
-                kiai_start = p.offset;
-            } else if !p.kiai_mode && kiai_on {
-                kiai_on = false;
-                kiai.push((kiai_start, p.offset));
-            }
-        }
-        if kiai_on {
-            kiai.push((kiai_start, timing_points.last().unwrap().offset));
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_files_ignoring_case() {
+        let dir = std::env::temp_dir().join(format!("osu_to_te_find_{}", std::process::id()));
+        fs::create_dir_all(dir.join("SB").join("Backgrounds")).unwrap();
+        fs::write(dir.join("SB").join("Backgrounds").join("BG.jpg"), []).unwrap();
+
+        let path = normalize_path(r#""sb\backgrounds\bg.JPG""#);
+        assert_eq!(
+            find_file(&dir, &path),
+            Some(dir.join("SB").join("Backgrounds").join("BG.jpg"))
+        );
+        assert_eq!(find_file(&dir, "sb/other.jpg"), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
 
-        kiai
+    #[test]
+    fn reads_maps_with_missing_background() {
+        let dir = std::env::temp_dir().join(format!("osu_to_te_read_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("audio.wav"), [1]).unwrap();
+        fs::write(
+            dir.join("map.osu"),
+            "osu file format v14\n\n\
+            [General]\nAudioFilename: Audio.WAV\n\n\
+            [Events]\n0,0,\"bg.jpg\",0,0\n\n\
+            [TimingPoints]\n0,500,4,2,1,50,1,0\n\n\
+            [HitObjects]\n256,192,1000,1,0\n",
+        )
+        .unwrap();
+
+        let map = Map::read(dir.join("map.osu")).unwrap();
+        assert_eq!(*map.audio, [1]);
+        // The audio isn't a hitsound, even if its name is written differently
+        assert!(map.samples.is_empty(), "{:?}", map.samples.keys());
+        assert!(map.thumb.is_none());
+        assert_eq!(map.warnings, [Warning::NoBackground]);
+        fs::remove_dir_all(dir).unwrap();
     }
 }
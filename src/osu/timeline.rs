@@ -0,0 +1,151 @@
+use anyhow::{anyhow, Result};
+use osuparse::TimingPoint;
+
+/// Timing information of a map, preprocessed for fast lookups by time.
+///
+/// osu! timing points are split into uninherited (red) lines, which set the
+/// beat length, and inherited (green) lines, which set the slider velocity
+/// multiplier until the next timing point.
+#[derive(Debug, Clone)]
+pub struct Timeline {
+    /// (offset, beat length in ms) of every uninherited point
+    beat_lengths: Vec<(f64, f64)>,
+    /// (offset, slider velocity multiplier) of every timing point
+    velocities: Vec<(f64, f64)>,
+    /// (start, end) of every kiai section
+    kiai: Vec<(f64, f64)>,
+    /// (offset, hitsound settings) of every timing point
+    samples: Vec<(f64, SamplePoint)>,
+}
+
+/// Hitsound settings of a timing point
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SamplePoint {
+    /// 0 for the map's default, 1 normal, 2 soft, 3 drum
+    pub sample_set: i32,
+    /// Custom sample index, 0 for the default samples
+    pub sample_index: i32,
+    /// Volume from 0 to 100
+    pub volume: i32,
+}
+
+impl Default for SamplePoint {
+    fn default() -> Self {
+        SamplePoint {
+            sample_set: 0,
+            sample_index: 0,
+            volume: 100,
+        }
+    }
+}
+
+impl Timeline {
+    pub fn new(timing_points: &[TimingPoint]) -> Result<Timeline> {
+        let mut points: Vec<&TimingPoint> = timing_points.iter().collect();
+        // Stable so points on the same offset keep their file order
+        points.sort_by(|a, b| a.offset.total_cmp(&b.offset));
+
+        let mut beat_lengths = Vec::new();
+        let mut velocities = Vec::new();
+        let mut kiai = Vec::new();
+        let mut kiai_start = None;
+        let mut samples = Vec::new();
+        for p in &points {
+            let offset = p.offset as f64;
+            let ms_per_beat = p.ms_per_beat as f64;
+            if ms_per_beat > 0. {
+                // Later red lines on the same offset override earlier ones
+                if matches!(beat_lengths.last(), Some((o, _)) if *o == offset) {
+                    beat_lengths.pop();
+                }
+                beat_lengths.push((offset, ms_per_beat));
+                // Red lines reset the slider velocity
+                velocities.push((offset, 1.));
+            } else if ms_per_beat < 0. {
+                velocities.push((offset, (100. / -ms_per_beat).clamp(0.1, 10.)));
+            }
+
+            samples.push((
+                offset,
+                SamplePoint {
+                    sample_set: p.sample_set,
+                    sample_index: p.sample_index,
+                    volume: p.volume,
+                },
+            ));
+
+            match (p.kiai_mode, kiai_start) {
+                (true, None) => kiai_start = Some(offset),
+                (false, Some(start)) => {
+                    kiai.push((start, offset));
+                    kiai_start = None;
+                }
+                _ => {}
+            }
+        }
+        if let (Some(start), Some(last)) = (kiai_start, points.last()) {
+            kiai.push((start, last.offset as f64));
+        }
+
+        if beat_lengths.is_empty() {
+            return Err(anyhow!("Map has no uninherited timing points"));
+        }
+
+        Ok(Timeline {
+            beat_lengths,
+            velocities,
+            kiai,
+            samples,
+        })
+    }
+
+    /// Beat length in ms at `time`. Times before the first uninherited point
+    /// use the first one, like osu! does.
+    pub fn beat_length_at(&self, time: f64) -> f64 {
+        Self::lookup(&self.beat_lengths, time).unwrap_or(self.beat_lengths[0].1)
+    }
+
+    /// (offset, beat length in ms) of the uninherited point active at `time`
+    pub fn red_line_at(&self, time: f64) -> (f64, f64) {
+        let i = self
+            .beat_lengths
+            .partition_point(|(offset, _)| *offset <= time);
+        self.beat_lengths[i.saturating_sub(1)]
+    }
+
+    /// (offset, beat length in ms) of every uninherited point, sorted by
+    /// offset and never empty
+    pub fn uninherited(&self) -> &[(f64, f64)] {
+        &self.beat_lengths
+    }
+
+    /// Slider velocity multiplier at `time`
+    pub fn sv_at(&self, time: f64) -> f64 {
+        Self::lookup(&self.velocities, time).unwrap_or(1.)
+    }
+
+    /// Whether `time` is inside a kiai section
+    pub fn kiai_at(&self, time: f64) -> bool {
+        let i = self.kiai.partition_point(|(start, _)| *start <= time);
+        i > 0 && time < self.kiai[i - 1].1
+    }
+
+    pub fn kiai_sections(&self) -> &[(f64, f64)] {
+        &self.kiai
+    }
+
+    /// Hitsound settings at `time`. Times before the first timing point use
+    /// the first one.
+    pub fn samples_at(&self, time: f64) -> SamplePoint {
+        let i = self.samples.partition_point(|(offset, _)| *offset <= time);
+        self.samples
+            .get(i.saturating_sub(1))
+            .map_or_else(SamplePoint::default, |(_, s)| *s)
+    }
+
+    /// Value of the last point at or before `time`
+    fn lookup(points: &[(f64, f64)], time: f64) -> Option<f64> {
+        let i = points.partition_point(|(offset, _)| *offset <= time);
+        (i > 0).then(|| points[i - 1].1)
+    }
+}
@@ -0,0 +1,263 @@
+use serde_derive::{Deserialize, Serialize};
+
+use crate::te::{self, Disc, Note, NoteType};
+
+/// Minimum time in ms between a truncated hold and the next object
+const MIN_GAP: f64 = 30.;
+/// Truncated holds shorter than this many ms become normal notes
+const MIN_HOLD: f64 = 50.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OverlapPolicy {
+    /// Only log conflicts
+    Keep,
+    /// Shorten the hold so it ends before the next object. Objects at the
+    /// same time as the start of the previous one can't be fixed by that and
+    /// are removed.
+    Truncate,
+    /// Move the later object to the other disc, dropping it if that is busy
+    /// at any time the object covers
+    MoveToOtherDisc,
+    /// Remove the later object
+    Drop,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Resolution {
+    Kept,
+    Truncated { from: f64, to: f64 },
+    Moved,
+    Dropped,
+}
+
+/// An object that started before the previous object on its disc ended
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Conflict {
+    pub disc: Disc,
+    pub time: f64,
+    pub resolution: Resolution,
+}
+
+#[derive(Debug, Clone)]
+struct Placed {
+    disc: Disc,
+    object: te::Object,
+}
+
+/// Detects objects that overlap on the same disc and resolves them with
+/// `policy`. Both discs are rewritten sorted by time.
+pub fn resolve(
+    left: &mut Vec<Note>,
+    right: &mut Vec<Note>,
+    policy: OverlapPolicy,
+) -> Vec<Conflict> {
+    let mut objects: Vec<Placed> = placed(Disc::Left, left)
+        .chain(placed(Disc::Right, right))
+        .collect();
+    // Stable, so the left disc goes first on equal times
+    objects.sort_by(|a, b| a.object.start.time.total_cmp(&b.object.start.time));
+    // Start times of every object on each disc, to see whether an object
+    // moved over would cover one that comes later
+    let starts = [Disc::Left, Disc::Right].map(|disc| {
+        objects
+            .iter()
+            .filter(|o| o.disc == disc)
+            .map(|o| o.object.start.time)
+            .collect::<Vec<f64>>()
+    });
+
+    let mut conflicts = Vec::new();
+    let mut kept: Vec<Placed> = Vec::with_capacity(objects.len());
+    // Index of the object in `kept` that ends last on each disc
+    let mut last: [Option<usize>; 2] = [None, None];
+    for mut o in objects {
+        let time = o.object.start.time;
+        let busy =
+            |disc: Disc| last[disc_index(disc)].filter(|p| time <= kept[*p].object.end_time());
+
+        if let Some(prev) = busy(o.disc) {
+            let other = disc_index(o.disc.other());
+            let end = o.object.end_time();
+            let other_free = busy(o.disc.other()).is_none() && {
+                let later = &starts[other][starts[other].partition_point(|s| *s < time)..];
+                later.first().is_none_or(|s| *s > end)
+            };
+            let prev_start = kept[prev].object.start.time;
+            let resolution = match policy {
+                OverlapPolicy::Keep => Resolution::Kept,
+                OverlapPolicy::Truncate if kept[prev].object.end.is_some() && time > prev_start => {
+                    let from = kept[prev].object.end_time();
+                    let to = (time - MIN_GAP).max(prev_start);
+                    if to - prev_start < MIN_HOLD {
+                        kept[prev].object.start.note_type = NoteType::Normal;
+                        kept[prev].object.end = None;
+                    } else if let Some(end) = &mut kept[prev].object.end {
+                        end.time = to;
+                    }
+                    Resolution::Truncated { from, to }
+                }
+                // Nothing to shorten when both start at the same time
+                OverlapPolicy::Truncate => Resolution::Dropped,
+                OverlapPolicy::MoveToOtherDisc if other_free => Resolution::Moved,
+                OverlapPolicy::MoveToOtherDisc | OverlapPolicy::Drop => Resolution::Dropped,
+            };
+
+            log::warn!(
+                "Overlap on {:?} disc at {:.0}ms: {:?}",
+                o.disc,
+                time,
+                resolution
+            );
+            conflicts.push(Conflict {
+                disc: o.disc,
+                time,
+                resolution: resolution.clone(),
+            });
+            match resolution {
+                Resolution::Dropped => continue,
+                Resolution::Moved => o.disc = o.disc.other(),
+                _ => {}
+            }
+        }
+
+        let i = disc_index(o.disc);
+        if last[i].is_none_or(|p| o.object.end_time() >= kept[p].object.end_time()) {
+            last[i] = Some(kept.len());
+        }
+        kept.push(o);
+    }
+
+    left.clear();
+    right.clear();
+    for o in kept {
+        let notes = match o.disc {
+            Disc::Left => &mut *left,
+            Disc::Right => &mut *right,
+        };
+        notes.push(o.object.start);
+        notes.extend(o.object.end);
+    }
+    // Kept overlaps put hold ends after notes inside the hold
+    left.sort_by(|a, b| a.time.total_cmp(&b.time));
+    right.sort_by(|a, b| a.time.total_cmp(&b.time));
+    conflicts
+}
+
+fn disc_index(disc: Disc) -> usize {
+    match disc {
+        Disc::Left => 0,
+        Disc::Right => 1,
+    }
+}
+
+fn placed(disc: Disc, notes: &[Note]) -> impl Iterator<Item = Placed> {
+    te::objects(notes)
+        .into_iter()
+        .map(move |object| Placed { disc, object })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(time: f64, note_type: NoteType) -> Note {
+        Note {
+            time,
+            note_type,
+            angle: 0.,
+        }
+    }
+
+    fn hold(start: f64, end: f64) -> [Note; 2] {
+        [
+            note(start, NoteType::HoldStart),
+            note(end, NoteType::HoldEnd),
+        ]
+    }
+
+    fn times(notes: &[Note]) -> Vec<f64> {
+        notes.iter().map(|n| n.time).collect()
+    }
+
+    fn resolutions(conflicts: &[Conflict]) -> Vec<Resolution> {
+        conflicts.iter().map(|c| c.resolution.clone()).collect()
+    }
+
+    #[test]
+    fn keep_only_reports() {
+        let mut left = [hold(0., 1000.).as_slice(), &[note(500., NoteType::Normal)]].concat();
+        let mut right = Vec::new();
+        let conflicts = resolve(&mut left, &mut right, OverlapPolicy::Keep);
+        assert_eq!(resolutions(&conflicts), vec![Resolution::Kept]);
+        assert_eq!(times(&left), vec![0., 500., 1000.]);
+    }
+
+    #[test]
+    fn truncate_shortens_holds() {
+        let mut left = [
+            hold(0., 1000.).as_slice(),
+            &[note(500., NoteType::Normal)],
+            &hold(600., 1000.),
+            &[note(620., NoteType::Normal)],
+        ]
+        .concat();
+        let mut right = Vec::new();
+        let conflicts = resolve(&mut left, &mut right, OverlapPolicy::Truncate);
+        assert_eq!(
+            resolutions(&conflicts),
+            vec![
+                Resolution::Truncated {
+                    from: 1000.,
+                    to: 470.
+                },
+                Resolution::Truncated {
+                    from: 1000.,
+                    to: 600.
+                },
+            ]
+        );
+        // The second hold became too short and turned into a normal note
+        assert_eq!(times(&left), vec![0., 470., 500., 600., 620.]);
+        assert_eq!(left[3].note_type, NoteType::Normal);
+    }
+
+    #[test]
+    fn truncate_drops_objects_at_the_same_time() {
+        let mut left = [hold(0., 1000.).as_slice(), &[note(0., NoteType::Normal)]].concat();
+        let mut right = Vec::new();
+        let conflicts = resolve(&mut left, &mut right, OverlapPolicy::Truncate);
+        assert_eq!(resolutions(&conflicts), vec![Resolution::Dropped]);
+        assert_eq!(times(&left), vec![0., 1000.]);
+    }
+
+    #[test]
+    fn move_checks_the_whole_object() {
+        // The first hold fits on the right disc, the second would cover the
+        // note at 2500ms there
+        let mut left = [
+            hold(0., 1000.).as_slice(),
+            &hold(500., 800.),
+            &hold(1500., 3000.),
+            &hold(2000., 2800.),
+        ]
+        .concat();
+        let mut right = vec![note(2500., NoteType::Normal)];
+        let conflicts = resolve(&mut left, &mut right, OverlapPolicy::MoveToOtherDisc);
+        assert_eq!(
+            resolutions(&conflicts),
+            vec![Resolution::Moved, Resolution::Dropped]
+        );
+        assert_eq!(times(&left), vec![0., 1000., 1500., 3000.]);
+        assert_eq!(times(&right), vec![500., 800., 2500.]);
+    }
+
+    #[test]
+    fn drop_removes_later_objects() {
+        let mut left = [hold(0., 1000.).as_slice(), &[note(500., NoteType::Normal)]].concat();
+        let mut right = vec![note(500., NoteType::Normal)];
+        let conflicts = resolve(&mut left, &mut right, OverlapPolicy::Drop);
+        assert_eq!(resolutions(&conflicts), vec![Resolution::Dropped]);
+        assert_eq!(times(&left), vec![0., 1000.]);
+        assert_eq!(times(&right), vec![500.]);
+    }
+}
@@ -0,0 +1,144 @@
+use serde_derive::Serialize;
+
+use crate::{
+    osu::Timeline,
+    te::{Disc, Note, NoteType},
+};
+
+/// A note that was moved by more than the tolerance while snapping
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SnapMove {
+    pub disc: Disc,
+    pub note_type: NoteType,
+    pub from: f64,
+    pub to: f64,
+}
+
+/// Snaps `time` to the closest 1/d beat of any divisor d in `divisors`,
+/// counted from the red line active at `time`
+pub fn snap_time(timeline: &Timeline, divisors: &[u32], time: f64) -> f64 {
+    let (offset, beat_length) = timeline.red_line_at(time);
+    divisors
+        .iter()
+        .filter(|d| **d > 0)
+        .map(|d| {
+            let step = beat_length / *d as f64;
+            offset + ((time - offset) / step).round() * step
+        })
+        .min_by(|a, b| (a - time).abs().total_cmp(&(b - time).abs()))
+        .unwrap_or(time)
+}
+
+/// Length of the finest grid step of `divisors` at `time`
+fn snap_step(timeline: &Timeline, divisors: &[u32], time: f64) -> f64 {
+    let (_, beat_length) = timeline.red_line_at(time);
+    beat_length / divisors.iter().copied().max().unwrap_or(1).max(1) as f64
+}
+
+/// Snaps every note on a disc to the beat grid and returns the notes that
+/// moved by more than `tolerance` ms
+pub fn quantize(
+    notes: &mut [Note],
+    disc: Disc,
+    timeline: &Timeline,
+    divisors: &[u32],
+    tolerance: f64,
+) -> Vec<SnapMove> {
+    let mut moves = Vec::new();
+    let mut hold_start = None;
+    for note in notes {
+        let mut snapped = snap_time(timeline, divisors, note.time);
+        match note.note_type {
+            NoteType::HoldStart => hold_start = Some(snapped),
+            // Don't let a hold collapse into nothing, keep it at least one
+            // grid step long
+            NoteType::HoldEnd => {
+                if let Some(start) = hold_start.filter(|s| snapped <= *s) {
+                    snapped = start + snap_step(timeline, divisors, start);
+                }
+            }
+            _ => {}
+        }
+
+        if (snapped - note.time).abs() > tolerance {
+            moves.push(SnapMove {
+                disc,
+                note_type: note.note_type,
+                from: note.time,
+                to: snapped,
+            });
+        }
+        note.time = snapped;
+    }
+    moves
+}
+
+#[cfg(test)]
+mod tests {
+    use osuparse::TimingPoint;
+
+    use super::*;
+
+    fn timeline() -> Timeline {
+        let red_line = |offset, ms_per_beat| TimingPoint {
+            offset,
+            ms_per_beat,
+            meter: 4,
+            sample_set: 0,
+            sample_index: 0,
+            volume: 100,
+            inherited: false,
+            kiai_mode: false,
+        };
+        // 120 BPM from 100ms, 150 BPM from 10100ms
+        Timeline::new(&[red_line(100., 500.), red_line(10100., 400.)]).unwrap()
+    }
+
+    fn note(time: f64, note_type: NoteType) -> Note {
+        Note {
+            time,
+            note_type,
+            angle: 0.,
+        }
+    }
+
+    #[test]
+    fn snaps_to_closest_divisor() {
+        let timeline = timeline();
+        assert_eq!(snap_time(&timeline, &[4], 240.), 225.);
+        // 1/3 is closer than 1/4
+        assert_eq!(snap_time(&timeline, &[3, 4], 270.), 100. + 500. / 3.);
+        // Counted from the second red line
+        assert_eq!(snap_time(&timeline, &[1], 10490.), 10500.);
+        assert_eq!(snap_time(&timeline, &[], 240.), 240.);
+    }
+
+    #[test]
+    fn reports_far_moves() {
+        let mut notes = vec![note(101., NoteType::Normal), note(240., NoteType::Reverse)];
+        let moves = quantize(&mut notes, Disc::Left, &timeline(), &[4], 3.);
+        assert_eq!(notes[0].time, 100.);
+        assert_eq!(notes[1].time, 225.);
+        assert_eq!(
+            moves,
+            vec![SnapMove {
+                disc: Disc::Left,
+                note_type: NoteType::Reverse,
+                from: 240.,
+                to: 225.,
+            }]
+        );
+    }
+
+    #[test]
+    fn holds_keep_a_length() {
+        // Both ends snap to 100ms
+        let mut notes = vec![
+            note(110., NoteType::HoldStart),
+            note(140., NoteType::HoldEnd),
+        ];
+        quantize(&mut notes, Disc::Right, &timeline(), &[2], 3.);
+        assert_eq!(notes[0].time, 100.);
+        assert_eq!(notes[1].time, 350.);
+    }
+}
@@ -0,0 +1,265 @@
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::te::{self, DifficultySettings, Disc, MapData, Note, NoteType, Object};
+
+/// Length of the sections strain is measured in
+const SECTION_LENGTH: f64 = 1000.;
+/// Weight falloff of sections sorted from hardest to easiest
+const DECAY: f64 = 0.9;
+/// Objects closer than this on different discs count as a disc switch
+const SWITCH_WINDOW: f64 = 500.;
+
+/// Star rating of a chart and the values it is made of
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rating {
+    pub stars: f64,
+    /// Weighted average of the densest sections in objects per second
+    pub density: f64,
+    /// Share of objects that follow an object on the other disc closely
+    pub switch_ratio: f64,
+    /// Share of objects that are hit while the other disc is holding
+    pub hold_overlap_ratio: f64,
+    pub reverse_ratio: f64,
+}
+
+impl Rating {
+    pub fn label(&self) -> String {
+        format!("{:.1}*", self.stars)
+    }
+}
+
+impl std::fmt::Display for Rating {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({:.1} notes/s, {:.0}% disc switches, {:.0}% during holds, {:.0}% reverse)",
+            self.label(),
+            self.density,
+            self.switch_ratio * 100.,
+            self.hold_overlap_ratio * 100.,
+            self.reverse_ratio * 100.
+        )
+    }
+}
+
+pub fn rate(left: &[Note], right: &[Note], settings: &DifficultySettings) -> Rating {
+    let discs = [te::objects(left), te::objects(right)];
+    let mut starts: Vec<(f64, usize)> = discs
+        .iter()
+        .enumerate()
+        .flat_map(|(d, objects)| objects.iter().map(move |o| (o.start.time, d)))
+        .collect();
+    starts.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let count = starts.len().max(1) as f64;
+
+    // Per disc object counts of every section
+    let first = starts.first().map_or(0., |s| s.0);
+    let mut sections: Vec<[f64; 2]> = Vec::new();
+    for (time, d) in &starts {
+        let i = ((time - first) / SECTION_LENGTH) as usize;
+        if sections.len() <= i {
+            sections.resize(i + 1, [0., 0.]);
+        }
+        sections[i][*d] += 1000. / SECTION_LENGTH;
+    }
+    // Both discs add up, but the busier one matters most
+    let mut strains: Vec<f64> = sections
+        .iter()
+        .map(|[l, r]| l.max(*r) + 0.5 * l.min(*r))
+        .collect();
+    strains.sort_by(|a, b| b.total_cmp(a));
+    let (weighted, weights) =
+        strains
+            .iter()
+            .enumerate()
+            .fold((0., 0.), |(sum, weights), (i, s)| {
+                let w = DECAY.powi(i as i32);
+                (sum + s * w, weights + w)
+            });
+    let density = if weights > 0. { weighted / weights } else { 0. };
+
+    let switches = starts
+        .windows(2)
+        .filter(|w| w[0].1 != w[1].1 && w[1].0 - w[0].0 < SWITCH_WINDOW)
+        .count();
+    let switch_ratio = switches as f64 / count;
+
+    let hold_overlaps: usize = (0..2)
+        .map(|d| {
+            let times: Vec<f64> = starts.iter().filter(|s| s.1 == d).map(|s| s.0).collect();
+            during_holds(&times, &discs[1 - d])
+        })
+        .sum();
+    let hold_overlap_ratio = hold_overlaps as f64 / count;
+
+    let reverses = discs
+        .iter()
+        .flatten()
+        .filter(|o| o.start.note_type == NoteType::Reverse)
+        .count();
+    let reverse_ratio = reverses as f64 / count;
+
+    // Less time to read notes and faster rotation make everything harder
+    let settings_factor = (0.5 / settings.note_appear_time.max(0.1)).sqrt()
+        * (settings.rotation_speed.max(1.) / 125.).powf(0.3);
+
+    let stars = 0.75
+        * density
+        * (1. + 0.3 * switch_ratio)
+        * (1. + 0.5 * hold_overlap_ratio)
+        * (1. + 0.4 * reverse_ratio)
+        * settings_factor;
+
+    Rating {
+        stars,
+        density,
+        switch_ratio,
+        hold_overlap_ratio,
+        reverse_ratio,
+    }
+}
+
+/// Number of `times` at which one of `objects` is being held. `times` has
+/// to be sorted.
+fn during_holds(times: &[f64], objects: &[Object]) -> usize {
+    let mut holds: Vec<(f64, f64)> = objects
+        .iter()
+        .filter(|o| o.end.is_some())
+        .map(|o| (o.start.time, o.end_time()))
+        .collect();
+    holds.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut next = 0;
+    let mut held_until = f64::MIN;
+    times
+        .iter()
+        .filter(|time| {
+            while holds.get(next).is_some_and(|h| h.0 < **time) {
+                held_until = held_until.max(holds[next].1);
+                next += 1;
+            }
+            **time < held_until
+        })
+        .count()
+}
+
+pub fn rate_map(data: &MapData) -> Result<Rating> {
+    Ok(rate(
+        &data.notes(Disc::Left)?,
+        &data.notes(Disc::Right)?,
+        &data.difficulty_settings,
+    ))
+}
+
+/// Name and rating of the difficulty and of every additional difficulty
+pub fn rate_all(data: &MapData) -> Result<Vec<(String, Rating)>> {
+    let mut ratings = vec![(data.difficulty_name.clone(), rate_map(data)?)];
+    for difficulty in &data.additional_difficulties {
+        let notes = |key: &str| te::parse_notes(difficulty[key].as_str().unwrap_or_default());
+        let settings = serde_json::from_value(difficulty["DifficultySettings"].clone())
+            .unwrap_or_else(|_| data.difficulty_settings.clone());
+        let rating = rate(
+            &notes("LeftDiscNotes")?,
+            &notes("RightDiscNotes")?,
+            &settings,
+        );
+        let name = difficulty["DifficultyName"].as_str().unwrap_or_default();
+        ratings.push((name.to_string(), rating));
+    }
+    Ok(ratings)
+}
+
+/// Appends the rating to the name of the difficulty and of every additional
+/// difficulty
+pub fn append_to_names(data: &mut MapData) -> Result<()> {
+    let mut ratings = rate_all(data)?.into_iter();
+    if let Some((name, rating)) = ratings.next() {
+        data.difficulty_name = format!("{name} {}", rating.label());
+    }
+    for (difficulty, (name, rating)) in data.additional_difficulties.iter_mut().zip(ratings) {
+        if let Some(difficulty) = difficulty.as_object_mut() {
+            let name = Value::from(format!("{name} {}", rating.label()));
+            difficulty.insert("DifficultyName".to_string(), name);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> DifficultySettings {
+        DifficultySettings {
+            note_appear_time: 0.5,
+            rotation_speed: 125.,
+            health_drain_per_second: 5.,
+        }
+    }
+
+    fn notes(times: &[f64], note_type: NoteType) -> Vec<Note> {
+        times.iter().map(|t| Note::new(*t, note_type)).collect()
+    }
+
+    #[test]
+    fn counts_ratios() {
+        let left = [
+            Note::new(0., NoteType::HoldStart),
+            Note::new(2000., NoteType::HoldEnd),
+        ];
+        let right = notes(&[100., 1000., 2000., 3000.], NoteType::Reverse);
+        let rating = rate(&left, &right, &settings());
+        // Only the notes at 100ms and 1000ms are hit during the hold
+        assert_eq!(rating.hold_overlap_ratio, 2. / 5.);
+        // The hold start to the note at 100ms
+        assert_eq!(rating.switch_ratio, 1. / 5.);
+        assert_eq!(rating.reverse_ratio, 4. / 5.);
+    }
+
+    #[test]
+    fn denser_charts_rate_higher() {
+        let sparse = notes(&[0., 1000., 2000., 3000.], NoteType::Normal);
+        let dense = notes(
+            &[0., 250., 500., 750., 1000., 1250., 1500., 1750.],
+            NoteType::Normal,
+        );
+        let sparse = rate(&sparse, &[], &settings());
+        let dense = rate(&dense, &[], &settings());
+        assert_eq!(sparse.density, 1.);
+        assert!(dense.stars > sparse.stars);
+
+        let faster = rate(
+            &notes(&[0., 1000., 2000., 3000.], NoteType::Normal),
+            &[],
+            &DifficultySettings {
+                note_appear_time: 0.25,
+                ..settings()
+            },
+        );
+        assert!(faster.stars > sparse.stars);
+        assert_eq!(rate(&[], &[], &settings()).stars, 0.);
+    }
+
+    #[test]
+    fn appends_ratings_to_every_difficulty() {
+        let mut data = MapData {
+            difficulty_name: "Hard".to_string(),
+            difficulty_settings: settings(),
+            left_disc_notes: te::format_notes(&notes(&[0., 500., 1000.], NoteType::Normal)),
+            additional_difficulties: vec![serde_json::json!({
+                "DifficultyName": "Easy",
+                "LeftDiscNotes": "0:0:0",
+                "RightDiscNotes": "",
+            })],
+            ..Default::default()
+        };
+        let stars = rate_map(&data).unwrap().label();
+        append_to_names(&mut data).unwrap();
+        assert_eq!(data.difficulty_name, format!("Hard {stars}"));
+        assert_eq!(
+            data.additional_difficulties[0]["DifficultyName"],
+            "Easy 0.8*"
+        );
+    }
+}
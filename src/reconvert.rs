@@ -0,0 +1,418 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+use crate::{
+    convert::{self, Options},
+    error::Warning,
+    osu,
+    report::Report,
+    te::{self, Source, ZipOptions},
+};
+
+/// Conversion info of a package, which only maps converted from osu! have
+pub fn source(package: &te::Map) -> Result<&Source> {
+    package
+        .source
+        .as_ref()
+        .ok_or_else(|| anyhow!("The map has no conversion info, it wasn't converted from osu!"))
+}
+
+/// Options `source` was converted with, with the fields in `changes`
+/// replaced
+pub fn options(source: &Source, changes: &Value) -> Result<Options> {
+    let mut options = match &source.options {
+        Value::Object(options) => options.clone(),
+        _ => Default::default(),
+    };
+    if let Value::Object(changes) = changes {
+        let known = serde_json::to_value(Options::default())?;
+        for (key, value) in changes {
+            if known.get(key).is_none() {
+                return Err(anyhow!("Unknown option {key:?}"));
+            }
+            options.insert(key.clone(), value.clone());
+        }
+    }
+    Ok(serde_json::from_value(Value::Object(options))?)
+}
+
+/// The fields `keys` of `options`, as changes. Used for the options the
+/// user changed in a form, so the recorded options are kept for everything
+/// else, even where the form shows a different value.
+pub fn fields(options: &Options, keys: &[&str]) -> Result<Value> {
+    let mut changes = serde_json::Map::new();
+    if let Value::Object(mut options) = serde_json::to_value(options)? {
+        for key in keys {
+            let value = options
+                .remove(*key)
+                .ok_or_else(|| anyhow!("Unknown option {key:?}"))?;
+            changes.insert(key.to_string(), value);
+        }
+    }
+    Ok(Value::Object(changes))
+}
+
+/// A change of one option written as `key=value`. Values that aren't JSON,
+/// like the names of variants, are taken as strings.
+pub fn parse_change(change: &str) -> Result<(String, Value)> {
+    let (key, value) = change
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Expected option=value, got {change:?}"))?;
+    let value = serde_json::from_str(value).unwrap_or_else(|_| Value::from(value));
+    Ok((key.trim().to_string(), value))
+}
+
+/// The difficulty `source` was converted from out of the maps of its set
+pub fn find_difficulty(source: &Source, maps: Vec<osu::Map>) -> Result<osu::Map> {
+    maps.into_iter()
+        .find(|m| m.data.metadata.beatmap_id == source.beatmap_id)
+        .ok_or_else(|| {
+            anyhow!(
+                "Difficulty {} is not in the beatmap set {} anymore",
+                source.beatmap_id,
+                source.beatmap_set_id
+            )
+        })
+}
+
+/// Converts `osu_map` again with the options of `package` changed by
+/// `changes`
+pub fn reconvert(
+    package: &te::Map,
+    osu_map: &osu::Map,
+    changes: &Value,
+) -> Result<(te::Map, Report)> {
+    let source = source(package)?;
+    let (map, mut report) = convert::convert(osu_map, &options(source, changes)?)?;
+    if osu_map.md5 != source.osu_md5 {
+        report.warnings.push(Warning::SourceChanged);
+    }
+    Ok((map, report))
+}
+
+/// Path of the .osu file `source` was converted from in an osu! Songs
+/// folder. The folder of the set is searched first. If the map was updated
+/// since, the difficulty with the same beatmap ID or name in the folder of
+/// the set is used, and `reconvert` warns that the source changed.
+pub fn locate(source: &Source, songs_dir: &Path) -> Result<PathBuf> {
+    let prefix = format!("{} ", source.beatmap_set_id);
+    let in_set = |p: &Path| {
+        p.file_name()
+            .is_some_and(|n| n.to_string_lossy().starts_with(&prefix))
+    };
+    let mut sets: Vec<PathBuf> = songs_dir
+        .read_dir()?
+        .filter_map(|f| f.ok())
+        .map(|f| f.path())
+        .filter(|p| p.is_dir())
+        .collect();
+    sets.sort_by_key(|p| !in_set(p));
+
+    let osu_files: Vec<PathBuf> = sets
+        .iter()
+        .filter_map(|set| set.read_dir().ok())
+        .flatten()
+        .filter_map(|f| f.ok())
+        .map(|f| f.path())
+        .filter(|p| p.extension().is_some_and(|e| e == "osu"))
+        .collect();
+    if let Some(path) = osu_files
+        .iter()
+        .find(|p| fs::read(p).is_ok_and(|d| format!("{:x}", md5::compute(d)) == source.osu_md5))
+    {
+        return Ok(path.clone());
+    }
+
+    let updated: Vec<(&PathBuf, osuparse::Beatmap)> = osu_files
+        .iter()
+        .filter(|p| p.parent().is_some_and(in_set))
+        .filter_map(|p| {
+            let text = fs::read_to_string(p).ok()?;
+            Some((p, osuparse::parse_beatmap(&text).ok()?))
+        })
+        .collect();
+    // Unsubmitted maps all have the ID 0
+    let by_id = updated
+        .iter()
+        .find(|(_, m)| source.beatmap_id != 0 && m.metadata.beatmap_id == source.beatmap_id);
+    let by_name = || {
+        updated
+            .iter()
+            .find(|(_, m)| m.metadata.version == source.difficulty)
+    };
+    by_id
+        .or_else(by_name)
+        .map(|(p, _)| (*p).clone())
+        .ok_or_else(|| {
+            anyhow!(
+                "Could not find the .osu file of {} in {}",
+                source.url,
+                songs_dir.display()
+            )
+        })
+}
+
+/// Converts the map zip or installed song folder at `path` again from the
+/// osu! Songs folder and overwrites it
+pub fn reconvert_path(
+    path: &Path,
+    songs_dir: &Path,
+    changes: &Value,
+    zip_options: ZipOptions,
+) -> Result<(te::Map, Report)> {
+    if path.is_dir() {
+        reconvert_dir(path, songs_dir, changes)
+    } else {
+        reconvert_zip(path, songs_dir, changes, zip_options)
+    }
+}
+
+/// Converts the installed song in `dir` again from the osu! Songs folder
+/// and replaces it. The new version is written to a folder next to it
+/// first, so a failed conversion leaves the old one as it was.
+pub fn reconvert_dir(dir: &Path, songs_dir: &Path, changes: &Value) -> Result<(te::Map, Report)> {
+    let package = te::Map::read_dir(dir)?;
+    let osu_map = osu::Map::read(locate(source(&package)?, songs_dir)?)?;
+    let (map, report) = reconvert(&package, &osu_map, changes)?;
+
+    let new_dir = sibling(dir, "new")?;
+    let old_dir = sibling(dir, "old")?;
+    if new_dir.exists() {
+        fs::remove_dir_all(&new_dir)?;
+    }
+    if let Err(e) = map.save_dir(&new_dir) {
+        let _ = fs::remove_dir_all(&new_dir);
+        return Err(e);
+    }
+    fs::rename(dir, &old_dir)?;
+    if let Err(e) = fs::rename(&new_dir, dir) {
+        fs::rename(&old_dir, dir)?;
+        return Err(e.into());
+    }
+    // Also removes files the new version doesn't use anymore, like the old
+    // audio after switching to WAV
+    fs::remove_dir_all(&old_dir)?;
+    Ok((map, report))
+}
+
+/// Converts the map zip at `path` again from the osu! Songs folder and
+/// replaces it
+pub fn reconvert_zip(
+    path: &Path,
+    songs_dir: &Path,
+    changes: &Value,
+    zip_options: ZipOptions,
+) -> Result<(te::Map, Report)> {
+    let package = te::Map::from_zip(&fs::read(path)?)?;
+    let osu_map = osu::Map::read(locate(source(&package)?, songs_dir)?)?;
+    let (map, report) = reconvert(&package, &osu_map, changes)?;
+    map.save_zip(path, zip_options)?;
+    Ok((map, report))
+}
+
+/// `path` with `.{suffix}` added to its file name
+fn sibling(path: &Path, suffix: &str) -> Result<PathBuf> {
+    let mut name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("{} has no name", path.display()))?
+        .to_os_string();
+    name.push(format!(".{suffix}"));
+    Ok(path.with_file_name(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{audio::Pcm, overlap::OverlapPolicy};
+
+    const OSU: &str = "osu file format v14\n\n\
+        [General]\nAudioFilename: audio.wav\n\n\
+        [Metadata]\nTitle:Song\nArtist:Artist\nCreator:Mapper\nVersion:Hard\nBeatmapID:456\nBeatmapSetID:123\n\n\
+        [Difficulty]\nSliderMultiplier:1\n\n\
+        [TimingPoints]\n0,500,4,2,1,50,1,0\n\n\
+        [HitObjects]\n256,192,1000,1,0\n256,192,2000,1,0\n";
+
+    /// Folder for a test with an osu! Songs folder holding the set of `OSU`
+    /// and another one, and a package converted from it
+    fn setup(name: &str) -> (PathBuf, PathBuf, te::Map) {
+        let root =
+            std::env::temp_dir().join(format!("osu_to_te_reconvert_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let songs = root.join("Songs");
+        let other = songs.join("1 Other - Song");
+        fs::create_dir_all(&other).unwrap();
+        fs::write(other.join("other.osu"), OSU.replace("Hard", "Easy")).unwrap();
+        let set = songs.join("123 Artist - Song");
+        fs::create_dir_all(&set).unwrap();
+        fs::write(set.join("Artist - Song (Mapper) [Hard].osu"), OSU).unwrap();
+        let audio = Pcm {
+            sample_rate: 8000,
+            channels: 1,
+            samples: vec![0.; 24000],
+        };
+        fs::write(set.join("audio.wav"), audio.to_wav().unwrap()).unwrap();
+
+        let osu_map = osu::Map::read(set.join("Artist - Song (Mapper) [Hard].osu")).unwrap();
+        let options = Options {
+            slider_beat_limit: 1.,
+            thumb_size: 16,
+            ..Default::default()
+        };
+        let (package, _) = convert::convert(&osu_map, &options).unwrap();
+        (root, songs, package)
+    }
+
+    fn recorded(package: &te::Map) -> Options {
+        serde_json::from_value(source(package).unwrap().options.clone()).unwrap()
+    }
+
+    #[test]
+    fn form_changes_keep_recorded_options() {
+        let form = Options {
+            overlap_policy: OverlapPolicy::Drop,
+            ..Default::default()
+        };
+        // The rate is set back to the default on purpose
+        let changes = fields(&form, &["overlap_policy", "rate"]).unwrap();
+        assert_eq!(changes, json!({ "overlap_policy": "Drop", "rate": 1.0 }));
+        assert!(fields(&form, &["slider_limit"]).is_err());
+
+        let source = Source {
+            options: serde_json::to_value(Options {
+                slider_beat_limit: 1.,
+                rate: 1.5,
+                ..Default::default()
+            })
+            .unwrap(),
+            ..Default::default()
+        };
+        let merged = options(&source, &changes).unwrap();
+        assert_eq!(merged.slider_beat_limit, 1.);
+        assert_eq!(merged.overlap_policy, OverlapPolicy::Drop);
+        assert_eq!(merged.rate, 1.);
+    }
+
+    #[test]
+    fn parses_command_line_changes() {
+        assert_eq!(
+            parse_change("slider_beat_limit=2").unwrap(),
+            ("slider_beat_limit".to_string(), json!(2))
+        );
+        assert_eq!(
+            parse_change("overlap_policy=Drop").unwrap(),
+            ("overlap_policy".to_string(), json!("Drop"))
+        );
+        assert!(parse_change("slider_beat_limit").is_err());
+        assert!(options(&Source::default(), &json!({ "slider_limit": 2 })).is_err());
+    }
+
+    #[test]
+    fn locates_the_difficulty() {
+        let (root, songs, package) = setup("locate");
+        let path = locate(source(&package).unwrap(), &songs).unwrap();
+        assert_eq!(
+            path,
+            songs
+                .join("123 Artist - Song")
+                .join("Artist - Song (Mapper) [Hard].osu")
+        );
+
+        // Updated since, found by ID and then by difficulty name
+        let changed = Source {
+            osu_md5: "0".repeat(32),
+            ..source(&package).unwrap().clone()
+        };
+        assert_eq!(locate(&changed, &songs).unwrap(), path);
+        let renumbered = Source {
+            beatmap_id: 789,
+            ..changed.clone()
+        };
+        assert_eq!(locate(&renumbered, &songs).unwrap(), path);
+        let missing = Source {
+            difficulty: "Insane".to_string(),
+            ..renumbered
+        };
+        assert!(locate(&missing, &songs).is_err());
+
+        let osu_map = osu::Map::read(&path).unwrap();
+        let package = te::Map {
+            source: Some(changed),
+            ..package
+        };
+        let (_, report) = reconvert(&package, &osu_map, &json!({})).unwrap();
+        assert!(report.warnings.contains(&Warning::SourceChanged));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn replaces_zips() {
+        let (root, songs, package) = setup("zip");
+        let path = root.join("Song.zip");
+        package.save_zip(&path, ZipOptions::default()).unwrap();
+
+        reconvert_zip(
+            &path,
+            &songs,
+            &json!({ "thumb_size": 8 }),
+            ZipOptions::default(),
+        )
+        .unwrap();
+        let map = te::Map::from_zip(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(map.thumb.width(), 8);
+        assert_eq!(recorded(&map).thumb_size, 8);
+        assert_eq!(recorded(&map).slider_beat_limit, 1.);
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 2, "temp file left");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn replaces_song_folders() {
+        let (root, songs, mut package) = setup("dir");
+        // Like a package whose audio was renamed by the new options
+        package.data.audio_file_name = "old.wav".to_string();
+        let dir = root.join("Song");
+        package.save_dir(&dir).unwrap();
+
+        reconvert_path(
+            &dir,
+            &songs,
+            &json!({ "thumb_size": 8 }),
+            ZipOptions::default(),
+        )
+        .unwrap();
+        let map = te::Map::read_dir(&dir).unwrap();
+        assert_eq!(map.thumb.width(), 8);
+        assert_eq!(recorded(&map).slider_beat_limit, 1.);
+        assert!(dir.join("audio.wav").exists());
+        assert!(!dir.join("old.wav").exists());
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 2, "temp folder left");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn changes_replace_recorded_options() {
+        let source = Source {
+            options: serde_json::to_value(Options {
+                slider_beat_limit: 1.,
+                thumb_size: 256,
+                ..Default::default()
+            })
+            .unwrap(),
+            ..Default::default()
+        };
+        let changed = options(&source, &json!({ "slider_beat_limit": 2.0 })).unwrap();
+        assert_eq!(changed.slider_beat_limit, 2.);
+        assert_eq!(changed.thumb_size, 256);
+
+        // Packages without recorded options use the defaults
+        let defaults = options(&Source::default(), &Value::Null).unwrap();
+        assert_eq!(defaults.thumb_size, 512);
+    }
+}
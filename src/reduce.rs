@@ -0,0 +1,286 @@
+use std::collections::VecDeque;
+
+use anyhow::Result;
+use serde_derive::Serialize;
+
+use crate::te::{self, Disc, MapData, NoteType, Object};
+
+/// Rules for removing notes from a chart to make it easier
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reduction {
+    pub difficulty_name: String,
+    /// Only keep objects on 1/d beats
+    pub max_divisor: Option<u32>,
+    /// Minimum time in ms between the end of an object and the next one on
+    /// the same disc
+    pub min_gap: f64,
+    /// Maximum number of objects on both discs within any second
+    pub max_notes_per_second: Option<usize>,
+    pub reverse_to_normal: bool,
+    pub note_appear_time: f64,
+}
+
+impl Reduction {
+    pub fn easy() -> Reduction {
+        Reduction {
+            difficulty_name: "Easy".to_string(),
+            max_divisor: Some(1),
+            min_gap: 400.,
+            max_notes_per_second: Some(2),
+            reverse_to_normal: true,
+            note_appear_time: 0.8,
+        }
+    }
+
+    pub fn normal() -> Reduction {
+        Reduction {
+            difficulty_name: "Normal".to_string(),
+            max_divisor: Some(2),
+            min_gap: 200.,
+            max_notes_per_second: Some(4),
+            reverse_to_normal: false,
+            note_appear_time: 0.65,
+        }
+    }
+}
+
+/// Entry of `MapData::additional_difficulties`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AdditionalDifficulty {
+    #[serde(rename = "DifficultyName")]
+    pub difficulty_name: String,
+    #[serde(rename = "DifficultySettings")]
+    pub difficulty_settings: te::DifficultySettings,
+    #[serde(rename = "RightDiscNotes")]
+    pub right_disc_notes: String,
+    #[serde(rename = "LeftDiscNotes")]
+    pub left_disc_notes: String,
+}
+
+/// Applies `reduction` to both discs of `data`. Returns the reduced
+/// difficulty and the number of removed objects.
+pub fn reduce(data: &MapData, reduction: &Reduction) -> Result<(AdditionalDifficulty, usize)> {
+    let mut discs = [
+        te::objects(&data.notes(Disc::Left)?),
+        te::objects(&data.notes(Disc::Right)?),
+    ];
+    let before: usize = discs.iter().map(Vec::len).sum();
+
+    for objects in &mut discs {
+        if let Some(divisor) = reduction.max_divisor {
+            objects.retain(|o| on_grid(data, divisor, o.start.time));
+        }
+
+        let mut free_from = f64::MIN;
+        objects.retain(|o| {
+            let keep = o.start.time >= free_from;
+            if keep {
+                free_from = o.end_time() + reduction.min_gap;
+            }
+            keep
+        });
+
+        if reduction.reverse_to_normal {
+            for o in objects.iter_mut() {
+                if o.start.note_type == NoteType::Reverse {
+                    o.start.note_type = NoteType::Normal;
+                }
+            }
+        }
+    }
+
+    if let Some(max) = reduction.max_notes_per_second {
+        cap_density(&mut discs, max);
+    }
+
+    let after: usize = discs.iter().map(Vec::len).sum();
+    let [left, right] = discs;
+    Ok((
+        AdditionalDifficulty {
+            difficulty_name: reduction.difficulty_name.clone(),
+            difficulty_settings: te::DifficultySettings {
+                note_appear_time: reduction.note_appear_time,
+                ..data.difficulty_settings.clone()
+            },
+            right_disc_notes: te::format_notes(&te::flatten(&right)),
+            left_disc_notes: te::format_notes(&te::flatten(&left)),
+        },
+        before - after,
+    ))
+}
+
+/// Generates the easy and normal reductions that remove any notes and adds
+/// them to the additional difficulties of `data`
+pub fn add_easier_difficulties(data: &mut MapData) -> Result<()> {
+    for reduction in [Reduction::easy(), Reduction::normal()] {
+        let (difficulty, removed) = reduce(data, &reduction)?;
+        if removed == 0 {
+            continue;
+        }
+        log::trace!(
+            "Generated {} difficulty with {} objects removed",
+            difficulty.difficulty_name,
+            removed
+        );
+        data.additional_difficulties
+            .push(serde_json::to_value(difficulty)?);
+    }
+    Ok(())
+}
+
+/// Whether `time` is within a few ms of a 1/`divisor` beat
+fn on_grid(data: &MapData, divisor: u32, time: f64) -> bool {
+    let (offset, beat_length) = data.beat_at(time);
+    let step = beat_length / divisor.max(1) as f64;
+    let distance = ((time - offset) / step - ((time - offset) / step).round()).abs() * step;
+    distance <= (step / 8.).min(10.)
+}
+
+/// Removes objects so no second contains more than `max` object starts
+fn cap_density(discs: &mut [Vec<Object>; 2], max: usize) {
+    let mut starts: Vec<(f64, usize, usize)> = discs
+        .iter()
+        .enumerate()
+        .flat_map(|(d, objects)| {
+            objects
+                .iter()
+                .enumerate()
+                .map(move |(i, o)| (o.start.time, d, i))
+        })
+        .collect();
+    starts.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut window = VecDeque::new();
+    let mut keep = [vec![true; discs[0].len()], vec![true; discs[1].len()]];
+    for (time, d, i) in starts {
+        while window.front().is_some_and(|t| time - t >= 1000.) {
+            window.pop_front();
+        }
+        if window.len() < max {
+            window.push_back(time);
+        } else {
+            keep[d][i] = false;
+        }
+    }
+
+    for (objects, keep) in discs.iter_mut().zip(keep) {
+        let mut keep = keep.into_iter();
+        objects.retain(|_| keep.next().unwrap());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::te::Note;
+
+    /// 120 BPM from 0ms
+    fn data(left: &[Note], right: &[Note]) -> MapData {
+        MapData {
+            bpm: 120.,
+            left_disc_notes: te::format_notes(left),
+            right_disc_notes: te::format_notes(right),
+            ..Default::default()
+        }
+    }
+
+    fn normal(times: &[f64]) -> Vec<Note> {
+        times
+            .iter()
+            .map(|t| Note::new(*t, NoteType::Normal))
+            .collect()
+    }
+
+    fn times(notes: &str) -> Vec<f64> {
+        te::parse_notes(notes)
+            .unwrap()
+            .iter()
+            .map(|n| n.time)
+            .collect()
+    }
+
+    fn reduction() -> Reduction {
+        Reduction {
+            difficulty_name: "Test".to_string(),
+            max_divisor: None,
+            min_gap: 0.,
+            max_notes_per_second: None,
+            reverse_to_normal: false,
+            note_appear_time: 1.,
+        }
+    }
+
+    #[test]
+    fn finds_grid_lines() {
+        let data = data(&[], &[]);
+        assert!(on_grid(&data, 1, 1000.));
+        assert!(on_grid(&data, 1, 1005.));
+        assert!(!on_grid(&data, 1, 1250.));
+        assert!(on_grid(&data, 2, 1250.));
+        assert!(!on_grid(&data, 2, 1125.));
+    }
+
+    #[test]
+    fn keeps_objects_on_the_grid() {
+        let data = data(&normal(&[0., 250., 500., 625., 1000.]), &normal(&[750.]));
+        let (difficulty, removed) = reduce(
+            &data,
+            &Reduction {
+                max_divisor: Some(2),
+                ..reduction()
+            },
+        )
+        .unwrap();
+        assert_eq!(removed, 1);
+        assert_eq!(
+            times(&difficulty.left_disc_notes),
+            vec![0., 250., 500., 1000.]
+        );
+        assert_eq!(times(&difficulty.right_disc_notes), vec![750.]);
+    }
+
+    #[test]
+    fn caps_density_across_discs() {
+        let data = data(
+            &normal(&[0., 200., 400., 600., 1100.]),
+            &normal(&[100., 300., 500.]),
+        );
+        let (difficulty, removed) = reduce(
+            &data,
+            &Reduction {
+                max_notes_per_second: Some(4),
+                ..reduction()
+            },
+        )
+        .unwrap();
+        // 0, 100, 200 and 300 fill the first second, 1100 is in a new one
+        assert_eq!(removed, 3);
+        assert_eq!(times(&difficulty.left_disc_notes), vec![0., 200., 1100.]);
+        assert_eq!(times(&difficulty.right_disc_notes), vec![100., 300.]);
+    }
+
+    #[test]
+    fn adds_only_easier_charts() {
+        let mut easy = data(&normal(&[0., 1000., 2000.]), &normal(&[500., 1500.]));
+        add_easier_difficulties(&mut easy).unwrap();
+        assert!(easy.additional_difficulties.is_empty());
+
+        let mut dense = data(&normal(&[0., 250., 500., 750.]), &normal(&[125., 375.]));
+        dense.left_disc_notes = format!("{}|1000:1:0", dense.left_disc_notes);
+        add_easier_difficulties(&mut dense).unwrap();
+        let names: Vec<_> = dense
+            .additional_difficulties
+            .iter()
+            .map(|d| d["DifficultyName"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["Easy", "Normal"]);
+        // Easy has no reverse notes
+        let easy_notes = dense.additional_difficulties[0]["LeftDiscNotes"]
+            .as_str()
+            .unwrap();
+        assert!(te::parse_notes(easy_notes)
+            .unwrap()
+            .iter()
+            .all(|n| n.note_type == NoteType::Normal));
+    }
+}
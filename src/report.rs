@@ -0,0 +1,256 @@
+use std::fmt::Display;
+
+use serde_derive::Serialize;
+
+use crate::{
+    error::Warning,
+    overlap::Conflict,
+    quantize::SnapMove,
+    sync::SyncCheck,
+    te::{self, Note, NoteType},
+};
+
+/// Number of density peaks listed in a report
+const PEAK_COUNT: usize = 5;
+
+/// What a conversion produced and everything it dropped or changed on the way
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Report {
+    pub osu_objects: OsuObjectCounts,
+    pub left_disc: NoteCounts,
+    pub right_disc: NoteCounts,
+    /// Start times of spinners that were left out
+    pub ignored_spinners: Vec<f64>,
+    /// Start times of sliders that were too short and became normal notes
+    pub sliders_to_notes: Vec<f64>,
+    /// Times of notes that became reverse notes because of their hitsounds
+    pub reverse_notes: Vec<f64>,
+    /// BPM that covers most of the song
+    pub main_bpm: f64,
+    pub skipped_timing_points: Vec<SkippedTimingPoint>,
+    pub snapped: Vec<SnapMove>,
+    pub overlaps: Vec<Conflict>,
+    /// Densest seconds of the chart, hardest first
+    pub density_peaks: Vec<DensityPeak>,
+    pub sync: Option<SyncCheck>,
+    /// Ms everything was moved by to line up with the audio
+    pub offset_correction: f64,
+    pub warnings: Vec<Warning>,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct OsuObjectCounts {
+    pub circles: usize,
+    pub sliders: usize,
+    pub spinners: usize,
+    pub hold_notes: usize,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct NoteCounts {
+    pub normal: usize,
+    pub reverse: usize,
+    pub holds: usize,
+}
+
+impl NoteCounts {
+    pub fn count(notes: &[Note]) -> NoteCounts {
+        let mut counts = NoteCounts::default();
+        for n in notes {
+            match n.note_type {
+                NoteType::Normal => counts.normal += 1,
+                NoteType::Reverse => counts.reverse += 1,
+                NoteType::HoldStart => counts.holds += 1,
+                NoteType::HoldEnd => {}
+            }
+        }
+        counts
+    }
+
+    pub fn total(&self) -> usize {
+        self.normal + self.reverse + self.holds
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedTimingPoint {
+    pub time: f64,
+    pub reason: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DensityPeak {
+    pub time: f64,
+    pub notes_per_second: usize,
+}
+
+impl Report {
+    /// Fills in the note counts and density peaks from the final notes
+    pub fn count_notes(&mut self, left: &[Note], right: &[Note]) {
+        self.left_disc = NoteCounts::count(left);
+        self.right_disc = NoteCounts::count(right);
+
+        let mut starts: Vec<f64> = te::objects(left)
+            .iter()
+            .chain(te::objects(right).iter())
+            .map(|o| o.start.time)
+            .collect();
+        starts.sort_by(|a, b| a.total_cmp(b));
+
+        // Objects in the second starting at every object
+        let mut windows: Vec<DensityPeak> = starts
+            .iter()
+            .enumerate()
+            .map(|(i, t)| DensityPeak {
+                time: *t,
+                notes_per_second: starts[i..].partition_point(|s| s - t < 1000.),
+            })
+            .collect();
+        windows.sort_by_key(|w| std::cmp::Reverse(w.notes_per_second));
+
+        self.density_peaks.clear();
+        for w in windows {
+            if self.density_peaks.len() >= PEAK_COUNT {
+                break;
+            }
+            if self
+                .density_peaks
+                .iter()
+                .all(|p| (p.time - w.time).abs() >= 1000.)
+            {
+                self.density_peaks.push(w);
+            }
+        }
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let o = &self.osu_objects;
+        let left = self.left_disc.total();
+        let right = self.right_disc.total();
+        write!(
+            f,
+            "{} circles, {} sliders, {} spinners, {} holds -> {} left / {} right ({:.0}% left)",
+            o.circles,
+            o.sliders,
+            o.spinners,
+            o.hold_notes,
+            left,
+            right,
+            left as f64 / (left + right).max(1) as f64 * 100.
+        )?;
+        write!(f, "; mostly {} BPM", self.main_bpm)?;
+        write!(
+            f,
+            "; {} sliders became notes, {} reverse notes, {} spinners ignored, {} timing points skipped, {} notes snapped, {} overlaps",
+            self.sliders_to_notes.len(),
+            self.reverse_notes.len(),
+            self.ignored_spinners.len(),
+            self.skipped_timing_points.len(),
+            self.snapped.len(),
+            self.overlaps.len()
+        )?;
+        if let Some(sync) = &self.sync {
+            write!(
+                f,
+                "; {} of {} notes match the audio",
+                sync.matched_notes, sync.notes
+            )?;
+        }
+        if self.offset_correction != 0. {
+            write!(
+                f,
+                "; moved by {:+.0}ms to match the audio",
+                self.offset_correction
+            )?;
+        }
+        if let Some(peak) = self.density_peaks.first() {
+            write!(
+                f,
+                "; densest second at {:.1}s with {} notes",
+                peak.time / 1000.,
+                peak.notes_per_second
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notes(times: &[f64]) -> Vec<Note> {
+        times
+            .iter()
+            .map(|t| Note::new(*t, NoteType::Normal))
+            .collect()
+    }
+
+    #[test]
+    fn counts_notes_per_disc() {
+        let left = vec![
+            Note::new(0., NoteType::Normal),
+            Note::new(100., NoteType::Reverse),
+            Note::new(200., NoteType::HoldStart),
+            Note::new(400., NoteType::HoldEnd),
+        ];
+        let mut report = Report::default();
+        report.count_notes(&left, &notes(&[50.]));
+        assert_eq!(report.left_disc.normal, 1);
+        assert_eq!(report.left_disc.reverse, 1);
+        assert_eq!(report.left_disc.holds, 1);
+        assert_eq!(report.left_disc.total(), 3);
+        assert_eq!(report.right_disc.total(), 1);
+    }
+
+    #[test]
+    fn finds_separate_density_peaks() {
+        // A burst of 6 objects, a quieter second of 3 and single notes
+        let left = notes(&[
+            0., 100., 200., 300., 400., 500., 3000., 3300., 3600., 6000., 8000.,
+        ]);
+        let mut report = Report::default();
+        report.count_notes(&left, &[]);
+        let peaks: Vec<(f64, usize)> = report
+            .density_peaks
+            .iter()
+            .map(|p| (p.time, p.notes_per_second))
+            .collect();
+        assert_eq!(peaks, vec![(0., 6), (3000., 3), (6000., 1), (8000., 1)]);
+    }
+
+    #[test]
+    fn lists_peaks_up_to_the_limit() {
+        let times: Vec<f64> = (0..10).map(|i| i as f64 * 2000.).collect();
+        let mut report = Report::default();
+        report.count_notes(&[], &notes(&times));
+        assert_eq!(report.density_peaks.len(), PEAK_COUNT);
+    }
+
+    #[test]
+    fn summarises_in_one_line() {
+        let mut report = Report {
+            main_bpm: 180.,
+            offset_correction: -12.,
+            ..Default::default()
+        };
+        report.osu_objects.circles = 3;
+        report.count_notes(&notes(&[0., 500.]), &notes(&[250.]));
+        let text = report.to_string();
+        assert!(
+            text.starts_with("3 circles, 0 sliders, 0 spinners, 0 holds -> 2 left / 1 right (67% left); mostly 180 BPM"),
+            "{text}"
+        );
+        assert!(
+            text.contains("; moved by -12ms to match the audio"),
+            "{text}"
+        );
+        assert!(
+            text.ends_with("; densest second at 0.0s with 3 notes"),
+            "{text}"
+        );
+        assert!(!text.contains('\n'));
+    }
+}
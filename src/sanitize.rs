@@ -0,0 +1,193 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::te::{self, MapData, Source, SOURCE_FILE_NAME};
+
+/// Longest file or folder name in bytes of UTF-8. Linux file systems allow
+/// 255 bytes, and CustomSongs is already deep inside AppData where Windows
+/// paths are limited to 260 characters.
+const MAX_NAME_LENGTH: usize = 100;
+/// Characters that aren't allowed in file names on Windows, or are path
+/// separators somewhere
+const FORBIDDEN: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+/// Names Windows reserves for devices, even with an extension
+const RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// `text` without control characters and with runs of whitespace turned
+/// into single spaces, for names shown in game
+pub fn text(text: &str) -> String {
+    text.split(|c: char| c.is_whitespace() || c.is_control())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `name` as a file or folder name that works on every platform and in
+/// zips. Forbidden characters become `_`, other unicode is kept, and long
+/// names are shortened without losing the extension.
+pub fn file_name(name: &str) -> String {
+    let name: String = text(name)
+        .chars()
+        .map(|c| if FORBIDDEN.contains(&c) { '_' } else { c })
+        .collect();
+    let name = name.trim_matches(|c: char| c == '.' || c == ' ');
+
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && extension.chars().count() <= 5 => {
+            (stem, Some(extension))
+        }
+        _ => (name, None),
+    };
+    let extension_length = extension.map_or(0, |e| e.len() + 1);
+    let stem = truncate(stem, MAX_NAME_LENGTH.saturating_sub(extension_length));
+    // Windows drops dots and spaces at the end of names
+    let mut stem = stem.trim_end_matches(['.', ' ']).to_string();
+    if stem.is_empty() {
+        stem = "_".to_string();
+    }
+    if RESERVED.iter().any(|r| r.eq_ignore_ascii_case(&stem)) {
+        stem.push('_');
+    }
+
+    match extension {
+        Some(extension) => format!("{stem}.{extension}"),
+        None => stem,
+    }
+}
+
+/// The longest start of `text` that is at most `max` bytes long
+fn truncate(text: &str, max: usize) -> &str {
+    let end = (0..=max.min(text.len()))
+        .rev()
+        .find(|i| text.is_char_boundary(*i))
+        .unwrap_or(0);
+    &text[..end]
+}
+
+/// Folder in `base` to install `map` into, named after the artist, title
+/// and mapper. A folder that holds another song with a name that ends up the
+/// same gets a number added instead of being overwritten.
+pub fn song_dir(base: &Path, map: &te::Map) -> PathBuf {
+    let data = &map.data;
+    let name = file_name(&format!(
+        "{} {} ({})",
+        data.artist, data.display_name, data.mapper_name
+    ));
+    let mut dir = base.join(&name);
+    let mut i = 2;
+    while dir.exists() && !holds_song(&dir, map) {
+        let suffix = format!(" ({i})");
+        let stem = truncate(&name, MAX_NAME_LENGTH - suffix.len());
+        dir = base.join(format!("{}{suffix}", stem.trim_end_matches(['.', ' '])));
+        i += 1;
+    }
+    dir
+}
+
+/// Whether the folder `dir` holds an older version of `map`. Maps converted
+/// from osu! are the same if they come from the same difficulty, others if
+/// their names match.
+fn holds_song(dir: &Path, map: &te::Map) -> bool {
+    let installed_source = fs::read_to_string(dir.join(SOURCE_FILE_NAME))
+        .ok()
+        .and_then(|s| serde_json::from_str::<Source>(&s).ok());
+    if let (Some(installed), Some(source)) = (installed_source, &map.source) {
+        return installed.beatmap_id == source.beatmap_id;
+    }
+
+    let Ok(files) = dir.read_dir() else {
+        return false;
+    };
+    let data = &map.data;
+    files
+        .filter_map(|f| f.ok())
+        .filter(|f| f.file_name().to_string_lossy().ends_with(".song"))
+        .filter_map(|f| serde_json::from_str::<MapData>(&fs::read_to_string(f.path()).ok()?).ok())
+        .any(|song| {
+            song.artist == data.artist
+                && song.display_name == data.display_name
+                && song.mapper_name == data.mapper_name
+                && song.difficulty_name == data.difficulty_name
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_forbidden_characters() {
+        assert_eq!(
+            file_name("What? / Why: \"Now\"*.song"),
+            "What_ _ Why_ _Now__.song"
+        );
+        assert_eq!(file_name("Trailing dots..."), "Trailing dots");
+        assert_eq!(file_name("con.song"), "con_.song");
+        assert_eq!(file_name("..."), "_");
+        assert_eq!(file_name("tab\there\n"), "tab here");
+    }
+
+    #[test]
+    fn keeps_unicode_and_extension() {
+        assert_eq!(
+            file_name("夜啼く兎は夢を見る.song"),
+            "夜啼く兎は夢を見る.song"
+        );
+        // 3 bytes each, so only whole characters fit before the extension
+        let long = file_name(&format!("{}.song", "あ".repeat(300)));
+        assert!(long.len() <= MAX_NAME_LENGTH);
+        assert_eq!(long.len(), 93 + ".song".len());
+        assert!(long.ends_with("あ.song"));
+    }
+
+    fn map(difficulty: &str, beatmap_id: Option<i32>) -> te::Map {
+        te::Map {
+            data: MapData {
+                song_file_name: "Song.song".to_string(),
+                audio_file_name: "audio.mp3".to_string(),
+                thumbnail_file_name: "thumb.png".to_string(),
+                artist: "あ".repeat(40),
+                display_name: "Song".to_string(),
+                mapper_name: "Mapper".to_string(),
+                difficulty_name: difficulty.to_string(),
+                ..Default::default()
+            },
+            audio: Vec::new().into(),
+            thumb: image::DynamicImage::new_rgb8(1, 1),
+            source: beatmap_id.map(|beatmap_id| Source {
+                beatmap_id,
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn separates_difficulties_of_a_set() {
+        let base = std::env::temp_dir().join(format!("osu_to_te_song_dir_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let hard = map("Hard", Some(1));
+        let first = song_dir(&base, &hard);
+        hard.save_dir(&first).unwrap();
+        assert!(first.file_name().unwrap().len() <= MAX_NAME_LENGTH);
+
+        // Updates of the same difficulty replace it, even if renamed
+        assert_eq!(song_dir(&base, &map("Hard 3.2*", Some(1))), first);
+        let insane = song_dir(&base, &map("Insane", Some(2)));
+        assert_ne!(insane, first);
+        let name = insane.file_name().unwrap().to_str().unwrap();
+        assert!(name.ends_with(" (2)"), "{name}");
+        assert!(name.len() <= MAX_NAME_LENGTH);
+
+        // Without conversion info the difficulty names are compared
+        let unconverted = map("Hard", None);
+        let dir = song_dir(&base, &unconverted);
+        assert_eq!(dir, first);
+        assert_ne!(song_dir(&base, &map("Insane", None)), first);
+        fs::remove_dir_all(base).unwrap();
+    }
+}
@@ -0,0 +1,157 @@
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+    onset::Analysis,
+    te::{self, Disc, MapData},
+};
+
+/// Notes further than this many ms from every onset aren't matched
+const MAX_ERROR: f64 = 150.;
+/// Differences this close to the most common one count towards the estimate
+const CLUSTER: f64 = 15.;
+/// Note errors larger than this many ms are reported
+const SUSPICIOUS_ERROR: f64 = 10.;
+/// Grid errors larger than this many ms are reported. The grid is estimated
+/// from fewer onsets, so it needs more room than the notes.
+const SUSPICIOUS_GRID_ERROR: f64 = 20.;
+/// Part of the notes that has to match an onset to trust the estimate
+const MIN_MATCHED: f64 = 0.3;
+
+/// Whether the chart is checked against the audio
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OffsetCheck {
+    Off,
+    /// Only report the estimated error
+    Report,
+    /// Apply the suggested correction as extra global offset
+    Correct,
+}
+
+/// How well the notes line up with the sounds in the audio
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SyncCheck {
+    /// Ms the notes would have to move to line up with the audio
+    pub note_error: f64,
+    /// Ms between the first beat offset and the beat grid found in the audio
+    pub grid_error: f64,
+    /// Notes close to an onset, out of `notes`
+    pub matched_notes: usize,
+    pub notes: usize,
+    /// The notes or the first beat seem shifted against the audio
+    pub suspicious: bool,
+}
+
+impl SyncCheck {
+    /// Whether the notes are off, not just the first beat offset
+    pub fn notes_shifted(&self) -> bool {
+        self.note_error.abs() > SUSPICIOUS_ERROR
+    }
+}
+
+/// Compares the start of every object of the main difficulty with the
+/// onsets in the audio. `None` if there are no notes near any onset.
+pub fn check(data: &MapData, analysis: &Analysis) -> Option<SyncCheck> {
+    let starts: Vec<f64> = [Disc::Left, Disc::Right]
+        .iter()
+        .filter_map(|d| data.notes(*d).ok())
+        .flat_map(|n| te::objects(&n))
+        .map(|o| o.start.time)
+        .collect();
+    let onsets: Vec<f64> = analysis.onsets.iter().map(|o| o.time).collect();
+
+    // Difference to the closest onset of every note that has one nearby
+    let differences: Vec<f64> = starts
+        .iter()
+        .filter_map(|t| {
+            let i = onsets.partition_point(|o| o < t);
+            [i.checked_sub(1), Some(i)]
+                .into_iter()
+                .flatten()
+                .filter_map(|i| onsets.get(i))
+                .map(|o| o - t)
+                .filter(|d| d.abs() <= MAX_ERROR)
+                .min_by(|a, b| a.abs().total_cmp(&b.abs()))
+        })
+        .collect();
+    if differences.is_empty() {
+        return None;
+    }
+
+    // Most common difference in 1ms steps, smoothed over neighbouring steps
+    let most_common = (-MAX_ERROR as i32..=MAX_ERROR as i32)
+        .map(|ms| {
+            let count = differences
+                .iter()
+                .filter(|d| (*d - ms as f64).abs() <= 5.)
+                .count();
+            (ms as f64, count)
+        })
+        .max_by_key(|(ms, count)| (*count, std::cmp::Reverse(ms.abs() as i64)))?
+        .0;
+    let mut cluster: Vec<f64> = differences
+        .iter()
+        .copied()
+        .filter(|d| (d - most_common).abs() <= CLUSTER)
+        .collect();
+    cluster.sort_by(|a, b| a.total_cmp(b));
+    let note_error = cluster[cluster.len() / 2];
+
+    let beat_length = 60000. / data.bpm;
+    let grid = analysis.grid_offset(beat_length);
+    let grid_error = (grid - data.first_beat_offset_in_ms as f64 + beat_length / 2.)
+        .rem_euclid(beat_length)
+        - beat_length / 2.;
+
+    let matched = cluster.len() as f64 / starts.len() as f64 >= MIN_MATCHED;
+    Some(SyncCheck {
+        note_error,
+        grid_error,
+        matched_notes: cluster.len(),
+        notes: starts.len(),
+        suspicious: matched
+            && (note_error.abs() > SUSPICIOUS_ERROR || grid_error.abs() > SUSPICIOUS_GRID_ERROR),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::onset;
+
+    #[test]
+    fn finds_shifted_notes() {
+        let analysis = Analysis::new(&onset::tests::click_track(120., 200., 10.));
+        // Every beat, but 40ms early
+        let notes: Vec<String> = (0..18).map(|i| format!("{}:0:0", 160 + i * 500)).collect();
+        let data = MapData {
+            bpm: 120.,
+            first_beat_offset_in_ms: 160,
+            left_disc_notes: notes.join("|"),
+            ..Default::default()
+        };
+
+        let sync = check(&data, &analysis).unwrap();
+        assert!(sync.suspicious);
+        assert_eq!(sync.notes, 18);
+        assert!((sync.note_error - 40.).abs() < 8., "{sync:?}");
+        assert!((sync.grid_error - 40.).abs() < 8., "{sync:?}");
+    }
+
+    #[test]
+    fn finds_shifted_first_beat() {
+        let analysis = Analysis::new(&onset::tests::click_track(120., 200., 10.));
+        // Every note is on a beat, but the first beat is 40ms early
+        let notes: Vec<String> = (0..18).map(|i| format!("{}:0:0", 200 + i * 500)).collect();
+        let data = MapData {
+            bpm: 120.,
+            first_beat_offset_in_ms: 160,
+            left_disc_notes: notes.join("|"),
+            ..Default::default()
+        };
+
+        let sync = check(&data, &analysis).unwrap();
+        assert!(sync.suspicious);
+        assert!(!sync.notes_shifted(), "{sync:?}");
+        assert!((sync.grid_error - 40.).abs() < 8., "{sync:?}");
+    }
+}
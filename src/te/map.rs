@@ -1,21 +1,63 @@
 use std::{
     env, fs,
-    io::{Cursor, Write},
-    path::Path,
+    io::{self, Cursor, Read, Seek, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use anyhow::{anyhow, Result};
 
-use image::{DynamicImage, ImageFormat};
+use image::{codecs::png::PngEncoder, ColorType, DynamicImage, ImageEncoder, ImageFormat};
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
+use serde_json::ser::{CompactFormatter, Formatter, PrettyFormatter};
 use serde_json::Value;
+use zip::{write::FileOptions, CompressionMethod, DateTime, ZipWriter};
+
+use super::{format_notes, parse_notes, Disc, Note, Source, SOURCE_FILE_NAME};
+use crate::sanitize;
+
+/// Decimal places floats are written to the .song file with
+const JSON_DECIMALS: i32 = 6;
+
+/// How the files in a map zip are compressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    /// Not compressed, the audio and thumbnail barely shrink anyway
+    #[default]
+    Stored,
+    /// Deflate at a level from 0 (fastest) to 9 (smallest)
+    Deflated(i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ZipOptions {
+    pub compression: Compression,
+}
+
+impl ZipOptions {
+    /// Options of every file in the zip. Dates and permissions are fixed so
+    /// the same map always gives the same zip.
+    pub fn file_options(self) -> FileOptions {
+        let file_options = FileOptions::default()
+            .last_modified_time(DateTime::default())
+            .unix_permissions(0o644);
+        match self.compression {
+            Compression::Stored => file_options.compression_method(CompressionMethod::Stored),
+            Compression::Deflated(level) => file_options
+                .compression_method(CompressionMethod::Deflated)
+                .compression_level(Some(level.clamp(0, 9))),
+        }
+    }
+}
 
 #[derive(Default, Debug, Clone, PartialEq)]
 pub struct Map {
     pub data: MapData,
-    pub audio: Vec<u8>,
+    pub audio: Arc<[u8]>,
     pub thumb: DynamicImage,
+    /// Where the map was converted from, None for maps made another way
+    pub source: Option<Source>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -117,17 +159,211 @@ pub struct Break {
     pub end_time_in_sec: f64,
 }
 
+impl MapData {
+    /// Contents of the .song file. Floats are rounded, so arithmetic noise
+    /// doesn't change the file between conversions of the same map.
+    pub fn to_json(&self) -> Result<String> {
+        stable_json(self)
+    }
+
+    pub fn notes(&self, disc: Disc) -> Result<Vec<Note>> {
+        parse_notes(match disc {
+            Disc::Left => &self.left_disc_notes,
+            Disc::Right => &self.right_disc_notes,
+        })
+    }
+
+    /// (offset, beat length in ms) of the BPM section active at `time`.
+    /// The first section starts at the first beat offset with the base BPM.
+    pub fn beat_at(&self, time: f64) -> (f64, f64) {
+        self.timing_points
+            .iter()
+            .filter(|p| p.time <= time && p.bpm > 0.)
+            .max_by(|a, b| a.time.total_cmp(&b.time))
+            .map_or(
+                (self.first_beat_offset_in_ms as f64, 60000. / self.bpm),
+                |p| (p.time, 60000. / p.bpm),
+            )
+    }
+
+    /// Changes every time in the song, including the notes of additional
+    /// difficulties, with `f`
+    pub fn map_times<F: Fn(f64) -> f64>(&mut self, f: F) -> Result<()> {
+        self.first_beat_offset_in_ms = f(self.first_beat_offset_in_ms as f64).round() as i64;
+        self.preview_time_seconds = f(self.preview_time_seconds * 1000.) / 1000.;
+        for p in &mut self.timing_points {
+            p.time = f(p.time);
+            p.time_in_sec = p.time / 1000.;
+        }
+
+        let map_notes = |notes: &str| -> Result<String> {
+            let mut notes = parse_notes(notes)?;
+            for n in &mut notes {
+                n.time = f(n.time);
+            }
+            Ok(format_notes(&notes))
+        };
+        self.left_disc_notes = map_notes(&self.left_disc_notes)?;
+        self.right_disc_notes = map_notes(&self.right_disc_notes)?;
+        // Entries that aren't objects have no notes to move
+        for difficulty in self
+            .additional_difficulties
+            .iter_mut()
+            .filter_map(Value::as_object_mut)
+        {
+            for key in ["LeftDiscNotes", "RightDiscNotes"] {
+                let notes = map_notes(
+                    difficulty
+                        .get(key)
+                        .and_then(Value::as_str)
+                        .unwrap_or_default(),
+                )?;
+                difficulty.insert(key.to_string(), Value::from(notes));
+            }
+        }
+
+        for e in &mut self.song_events {
+            e.time = f(e.time);
+        }
+        for s in &mut self.special_sections {
+            s.start_time = f(s.start_time);
+            s.end_time = f(s.end_time);
+            s.start_time_in_sec = s.start_time / 1000.;
+            s.end_time_in_sec = s.end_time / 1000.;
+        }
+        for b in &mut self.breaks {
+            b.start_time = f(b.start_time);
+            b.end_time = f(b.end_time);
+            b.start_time_in_sec = b.start_time / 1000.;
+            b.end_time_in_sec = b.end_time / 1000.;
+        }
+        Ok(())
+    }
+
+    /// Makes the song `rate` times as fast and adds the rate to the names of
+    /// all difficulties
+    pub fn change_rate(&mut self, rate: f64) -> Result<()> {
+        self.map_times(|t| t / rate)?;
+        self.bpm *= rate;
+        for p in &mut self.timing_points {
+            p.bpm *= rate;
+        }
+
+        self.difficulty_name = format!("{} {rate}x", self.difficulty_name);
+        for difficulty in self
+            .additional_difficulties
+            .iter_mut()
+            .filter_map(Value::as_object_mut)
+        {
+            let name = difficulty
+                .get("DifficultyName")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let name = Value::from(format!("{name} {rate}x"));
+            difficulty.insert("DifficultyName".to_string(), name);
+        }
+        Ok(())
+    }
+
+    /// Moves everything in the song by `ms`. A first beat that ends up
+    /// before 0ms is moved forward by whole beats.
+    pub fn shift(&mut self, ms: f64) -> Result<()> {
+        self.map_times(|t| t + ms)?;
+        if self.first_beat_offset_in_ms < 0 {
+            let offset = self.first_beat_offset_in_ms as f64;
+            let (_, beat_length) = self.beat_at(offset);
+            let offset = offset + (-offset / beat_length).ceil() * beat_length;
+            self.first_beat_offset_in_ms = offset.round() as i64;
+        }
+        Ok(())
+    }
+}
+
+/// Twin Edge's folder of installed songs
+fn custom_songs_dir() -> Result<PathBuf> {
+    let profile = env::var_os("userprofile").ok_or_else(|| anyhow!("No user profile folder"))?;
+    Ok(Path::new(&profile)
+        .join("AppData")
+        .join("LocalLow")
+        .join("Arcy")
+        .join("TwinEdge")
+        .join("CustomSongs"))
+}
+
+/// Pretty JSON of `value` that is the same for the same value on every
+/// platform
+pub fn stable_json<T: serde::Serialize>(value: &T) -> Result<String> {
+    let mut out = Vec::new();
+    let mut serializer =
+        serde_json::Serializer::with_formatter(&mut out, StableFormatter::default());
+    value.serialize(&mut serializer)?;
+    Ok(String::from_utf8(out)?)
+}
+
+/// Pretty JSON with floats rounded to `JSON_DECIMALS` and without negative
+/// zero
+#[derive(Default)]
+struct StableFormatter {
+    pretty: PrettyFormatter<'static>,
+}
+
+impl Formatter for StableFormatter {
+    fn write_f64<W: ?Sized + Write>(&mut self, writer: &mut W, value: f64) -> io::Result<()> {
+        let scale = 10f64.powi(JSON_DECIMALS);
+        // Adding zero turns -0 into 0
+        let rounded = (value * scale).round() / scale + 0.;
+        CompactFormatter.write_f64(writer, rounded)
+    }
+
+    fn begin_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.pretty.begin_array(writer)
+    }
+
+    fn end_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.pretty.end_array(writer)
+    }
+
+    fn begin_array_value<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        self.pretty.begin_array_value(writer, first)
+    }
+
+    fn end_array_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.pretty.end_array_value(writer)
+    }
+
+    fn begin_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.pretty.begin_object(writer)
+    }
+
+    fn end_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.pretty.end_object(writer)
+    }
+
+    fn begin_object_key<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        self.pretty.begin_object_key(writer, first)
+    }
+
+    fn begin_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.pretty.begin_object_value(writer)
+    }
+
+    fn end_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.pretty.end_object_value(writer)
+    }
+}
+
 impl Map {
-    #[allow(dead_code)]
+    /// Reads the installed song whose folder name contains `map_search`
     pub fn read(map_search: &str) -> Result<Map> {
-        let base_path = Path::new(&env::var_os("userprofile").unwrap())
-            .join("AppData")
-            .join("LocalLow")
-            .join("Arcy")
-            .join("TwinEdge")
-            .join("CustomSongs");
-
-        let map_dir = base_path
+        let map_dir = custom_songs_dir()?
             .read_dir()?
             .find(|f| {
                 f.as_ref()
@@ -138,9 +374,13 @@ impl Map {
                     .contains(map_search)
             })
             .ok_or_else(|| anyhow!("Could not find map"))??;
+        Self::read_dir(map_dir.path())
+    }
 
-        let data_file = map_dir
-            .path()
+    /// Reads the song in the folder `dir`
+    pub fn read_dir<P: AsRef<Path>>(dir: P) -> Result<Map> {
+        let dir = dir.as_ref();
+        let data_file = dir
             .read_dir()?
             .find(|f| {
                 f.as_ref()
@@ -152,86 +392,260 @@ impl Map {
             })
             .ok_or_else(|| anyhow!("Could not find .song file"))??;
 
-        let audio_file = map_dir
-            .path()
-            .read_dir()?
-            .find(|f| {
-                f.as_ref()
-                    .unwrap()
-                    .file_name()
-                    .into_string()
-                    .unwrap()
-                    .ends_with(".wav")
-            })
-            .ok_or_else(|| anyhow!("Could not find audio file"))??;
+        let data: MapData = serde_json::from_str(&fs::read_to_string(data_file.path())?)?;
+        let source = match fs::read_to_string(dir.join(SOURCE_FILE_NAME)) {
+            Ok(source) => Some(serde_json::from_str(&source)?),
+            Err(_) => None,
+        };
 
-        let thumb_file = map_dir
-            .path()
-            .read_dir()?
-            .find(|f| {
-                f.as_ref()
-                    .unwrap()
-                    .file_name()
-                    .into_string()
-                    .unwrap()
-                    .ends_with(".png")
-            })
-            .ok_or_else(|| anyhow!("Could not find thumb file"))??;
+        Ok(Map {
+            audio: fs::read(dir.join(&data.audio_file_name))?.into(),
+            thumb: image::load_from_memory(&fs::read(dir.join(&data.thumbnail_file_name))?)?,
+            data,
+            source,
+        })
+    }
 
-        let data = serde_json::from_str(&fs::read_to_string(data_file.path())?)?;
+    /// Reads a map zip, like the ones `as_zip` makes
+    pub fn from_zip(zip: &[u8]) -> Result<Map> {
+        let mut archive = zip::ZipArchive::new(Cursor::new(zip))?;
+        let song_file = archive
+            .file_names()
+            .find(|f| f.ends_with(".song"))
+            .ok_or_else(|| anyhow!("Could not find .song file"))?
+            .to_string();
+        let mut read = |name: &str| -> Result<Vec<u8>> {
+            let mut file = archive
+                .by_name(name)
+                .map_err(|_| anyhow!("Could not find {name} in the zip"))?;
+            let mut data = Vec::with_capacity(file.size() as _);
+            file.read_to_end(&mut data)?;
+            Ok(data)
+        };
+
+        let data: MapData = serde_json::from_slice(&read(&song_file)?)?;
+        let source = match read(SOURCE_FILE_NAME) {
+            Ok(source) => Some(serde_json::from_slice(&source)?),
+            Err(_) => None,
+        };
 
         Ok(Map {
-            audio: fs::read(audio_file.path())?,
+            audio: read(&data.audio_file_name)?.into(),
+            thumb: image::load_from_memory(&read(&data.thumbnail_file_name)?)?,
             data,
-            thumb: image::load_from_memory(&fs::read(thumb_file.path())?)?,
+            source,
         })
     }
 
+    /// Installs the song into the CustomSongs folder
     pub fn save(&self) -> Result<()> {
-        let base_path = Path::new(&env::var_os("userprofile").unwrap())
-            .join("AppData")
-            .join("LocalLow")
-            .join("Arcy")
-            .join("TwinEdge")
-            .join("CustomSongs");
-
-        let map_dir = base_path.join(format!(
-            "{} {} ({})",
-            self.data.artist, self.data.display_name, self.data.mapper_name
-        ));
-
-        fs::create_dir_all(&map_dir)?;
+        self.save_dir(sanitize::song_dir(&custom_songs_dir()?, self))
+    }
+
+    /// Writes the song files into the folder `dir`
+    pub fn save_dir<P: AsRef<Path>>(&self, dir: P) -> Result<()> {
+        let map_dir = dir.as_ref();
+        fs::create_dir_all(map_dir)?;
         fs::write(
             map_dir.join(&self.data.song_file_name),
-            serde_json::to_string_pretty(&self.data)?,
+            self.data.to_json()?,
         )?;
         fs::write(map_dir.join(&self.data.audio_file_name), &self.audio)?;
         self.thumb.save_with_format(
             map_dir.join(&self.data.thumbnail_file_name),
             ImageFormat::Png,
         )?;
+        if let Some(source) = &self.source {
+            fs::write(map_dir.join(SOURCE_FILE_NAME), stable_json(source)?)?;
+        }
 
         Ok(())
     }
 
-    pub fn as_zip(&self) -> Result<Vec<u8>> {
-        let mut cursor = Cursor::new(Vec::new());
-        {
-            let mut writer = zip::ZipWriter::new(&mut cursor);
-            let options = zip::write::FileOptions::default()
-                .compression_method(zip::CompressionMethod::Stored);
-
-            writer.start_file(&self.data.song_file_name, options)?;
-            writer.write_all(serde_json::to_string_pretty(&self.data)?.as_bytes())?;
-            writer.start_file(&self.data.audio_file_name, options)?;
-            writer.write_all(&self.audio)?;
-            writer.start_file(&self.data.thumbnail_file_name, options)?;
-            let mut img_cursor = Cursor::new(Vec::new());
-            self.thumb.write_to(&mut img_cursor, ImageFormat::Png)?;
-            writer.write_all(&img_cursor.into_inner())?;
-
-            writer.finish()?;
+    /// Saves the map as a zip at `path` without building it in memory first.
+    /// Only for native builds, the browser can't seek in a download, so
+    /// zips made there are built in memory with `as_zip`.
+    ///
+    /// The zip is written next to `path` and then moved over it, so an
+    /// existing file at `path` is only replaced once the new one is complete.
+    pub fn save_zip<P: AsRef<Path>>(&self, path: P, options: ZipOptions) -> Result<()> {
+        let path = path.as_ref();
+        let mut temp_name = path
+            .file_name()
+            .ok_or_else(|| anyhow!("{} is not a file", path.display()))?
+            .to_os_string();
+        temp_name.push(".tmp");
+        let temp = path.with_file_name(temp_name);
+
+        let saved = (|| -> Result<()> {
+            let file = self.write_zip(fs::File::create(&temp)?, options)?;
+            file.sync_all()?;
+            fs::rename(&temp, path)?;
+            Ok(())
+        })();
+        if saved.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        saved
+    }
+
+    pub fn as_zip(&self, options: ZipOptions) -> Result<Vec<u8>> {
+        Ok(self
+            .write_zip(Cursor::new(Vec::new()), options)?
+            .into_inner())
+    }
+
+    /// Writes the map as a zip to `writer` and returns it. The files are
+    /// written straight into it, the zip only has to seek back to fill in
+    /// the size of each file.
+    pub fn write_zip<W: Write + Seek>(&self, writer: W, options: ZipOptions) -> Result<W> {
+        let mut writer = ZipWriter::new(writer);
+        self.write_files(&mut writer, "", options)?;
+        Ok(writer.finish()?)
+    }
+
+    /// Adds the files of the map to `writer`, in the folder `dir` if it
+    /// isn't empty. They're always written as song, audio, thumbnail.
+    pub fn write_files<W: Write + Seek>(
+        &self,
+        writer: &mut ZipWriter<W>,
+        dir: &str,
+        options: ZipOptions,
+    ) -> Result<()> {
+        let file_options = options.file_options();
+        let path = |name: &str| {
+            if dir.is_empty() {
+                name.to_string()
+            } else {
+                format!("{dir}/{name}")
+            }
+        };
+
+        writer.start_file(path(&self.data.song_file_name), file_options)?;
+        writer.write_all(self.data.to_json()?.as_bytes())?;
+        writer.start_file(path(&self.data.audio_file_name), file_options)?;
+        writer.write_all(&self.audio)?;
+        writer.start_file(path(&self.data.thumbnail_file_name), file_options)?;
+        let thumb = self.thumb.to_rgba8();
+        PngEncoder::new(&mut *writer).write_image(
+            &thumb,
+            thumb.width(),
+            thumb.height(),
+            ColorType::Rgba8,
+        )?;
+        if let Some(source) = &self.source {
+            writer.start_file(path(SOURCE_FILE_NAME), file_options)?;
+            writer.write_all(stable_json(source)?.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map() -> Map {
+        Map {
+            data: MapData {
+                song_file_name: "Song.song".to_string(),
+                audio_file_name: "audio.mp3".to_string(),
+                thumbnail_file_name: "thumb.png".to_string(),
+                ..Default::default()
+            },
+            audio: vec![0; 10000].into(),
+            thumb: DynamicImage::new_rgb8(4, 4),
+            source: Some(Source {
+                beatmap_id: 1,
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn compression_round_trip() {
+        let map = map();
+        let stored = map.as_zip(ZipOptions::default()).unwrap();
+        let deflated = map
+            .as_zip(ZipOptions {
+                compression: Compression::Deflated(9),
+            })
+            .unwrap();
+        assert!(deflated.len() < stored.len());
+
+        for zip in [stored, deflated] {
+            let mut archive = zip::ZipArchive::new(Cursor::new(zip)).unwrap();
+            let mut audio = Vec::new();
+            archive
+                .by_name("audio.mp3")
+                .unwrap()
+                .read_to_end(&mut audio)
+                .unwrap();
+            assert_eq!(*audio, *map.audio);
+            let mut thumb = Vec::new();
+            archive
+                .by_name("thumb.png")
+                .unwrap()
+                .read_to_end(&mut thumb)
+                .unwrap();
+            assert_eq!(image::load_from_memory(&thumb).unwrap().width(), 4);
         }
-        Ok(cursor.into_inner())
+    }
+
+    #[test]
+    fn reads_its_own_zips() {
+        let map = map();
+        let read = Map::from_zip(&map.as_zip(ZipOptions::default()).unwrap()).unwrap();
+        assert_eq!(read.data, map.data);
+        assert_eq!(read.audio, map.audio);
+        assert_eq!(read.thumb.to_rgba8(), map.thumb.to_rgba8());
+        assert_eq!(read.source, map.source);
+
+        let mut without_source = map.clone();
+        without_source.source = None;
+        let zip = without_source.as_zip(ZipOptions::default()).unwrap();
+        assert_eq!(Map::from_zip(&zip).unwrap().source, None);
+        assert!(Map::from_zip(b"not a zip").is_err());
+    }
+
+    #[test]
+    fn zip_is_reproducible() {
+        let map = map();
+        let options = ZipOptions {
+            compression: Compression::Deflated(6),
+        };
+        assert_eq!(map.as_zip(options).unwrap(), map.as_zip(options).unwrap());
+    }
+
+    #[test]
+    fn json_rounds_floats() {
+        let data = MapData {
+            bpm: 0.1 + 0.2,
+            preview_time_seconds: -0.,
+            ..Default::default()
+        };
+        let json = data.to_json().unwrap();
+        assert!(json.contains("\"Bpm\": 0.3,"), "{json}");
+        assert!(json.contains("\"PreviewTimeSeconds\": 0.0,"), "{json}");
+        // Keys stay in the order of the fields
+        assert!(json.find("mapperName") < json.find("DisplayName"));
+        let read: MapData = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.bpm, 0.3);
+    }
+
+    #[test]
+    fn skips_difficulties_that_are_not_objects() {
+        let mut data = MapData {
+            difficulty_name: "Hard".to_string(),
+            additional_difficulties: vec![
+                Value::Null,
+                serde_json::json!({"DifficultyName": "Easy", "LeftDiscNotes": "1000:0:0"}),
+            ],
+            ..Default::default()
+        };
+        data.change_rate(2.).unwrap();
+        data.shift(100.).unwrap();
+        assert_eq!(data.additional_difficulties[0], Value::Null);
+        assert_eq!(data.additional_difficulties[1]["DifficultyName"], "Easy 2x");
     }
 }
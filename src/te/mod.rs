@@ -1,2 +1,6 @@
 mod map;
+mod note;
+mod source;
 pub use map::*;
+pub use note::*;
+pub use source::*;
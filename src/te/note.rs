@@ -0,0 +1,130 @@
+use std::fmt::Write as _;
+
+use anyhow::{anyhow, Result};
+use serde_derive::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Disc {
+    Left,
+    Right,
+}
+
+impl Disc {
+    pub fn other(self) -> Disc {
+        match self {
+            Disc::Left => Disc::Right,
+            Disc::Right => Disc::Left,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum NoteType {
+    Normal,
+    Reverse,
+    HoldStart,
+    HoldEnd,
+}
+
+/// A single entry of a disc note string, written as `time:type:angle`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Note {
+    /// Time in ms, rounded when written
+    pub time: f64,
+    pub note_type: NoteType,
+    pub angle: f64,
+}
+
+impl Note {
+    pub fn new(time: f64, note_type: NoteType) -> Note {
+        Note {
+            time,
+            note_type,
+            angle: 0.,
+        }
+    }
+}
+
+/// A normal or reverse note, or a hold with its end
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Object {
+    pub start: Note,
+    pub end: Option<Note>,
+}
+
+impl Object {
+    pub fn end_time(&self) -> f64 {
+        self.end.map_or(self.start.time, |n| n.time)
+    }
+}
+
+/// Groups the notes of a disc into objects. Unmatched hold notes are skipped.
+pub fn objects(notes: &[Note]) -> Vec<Object> {
+    let mut objects = Vec::new();
+    let mut hold_start = None;
+    for n in notes {
+        match n.note_type {
+            NoteType::Normal | NoteType::Reverse => objects.push(Object {
+                start: *n,
+                end: None,
+            }),
+            NoteType::HoldStart => hold_start = Some(*n),
+            NoteType::HoldEnd => {
+                if let Some(start) = hold_start.take() {
+                    objects.push(Object {
+                        start,
+                        end: Some(*n),
+                    })
+                }
+            }
+        }
+    }
+    objects
+}
+
+pub fn flatten(objects: &[Object]) -> Vec<Note> {
+    objects
+        .iter()
+        .flat_map(|o| std::iter::once(o.start).chain(o.end))
+        .collect()
+}
+
+pub fn parse_notes(notes: &str) -> Result<Vec<Note>> {
+    notes
+        .split('|')
+        .filter(|n| !n.trim().is_empty())
+        .map(|n| {
+            let mut parts = n.split(':');
+            let mut next = || parts.next().ok_or_else(|| anyhow!("Invalid note {n:?}"));
+            let time = next()?.trim().parse()?;
+            let note_type = match next()?.trim() {
+                "0" => NoteType::Normal,
+                "1" => NoteType::Reverse,
+                "2" => NoteType::HoldStart,
+                "3" => NoteType::HoldEnd,
+                t => return Err(anyhow!("Invalid note type {t:?} in {n:?}")),
+            };
+            let angle = next()?.trim().parse()?;
+            Ok(Note {
+                time,
+                note_type,
+                angle,
+            })
+        })
+        .collect()
+}
+
+pub fn format_notes(notes: &[Note]) -> String {
+    let mut out = String::new();
+    for n in notes {
+        let note_type = match n.note_type {
+            NoteType::Normal => 0,
+            NoteType::Reverse => 1,
+            NoteType::HoldStart => 2,
+            NoteType::HoldEnd => 3,
+        };
+        write!(out, "{}:{}:{}|", n.time.round() as i64, note_type, n.angle).unwrap();
+    }
+    out.pop();
+    out
+}
@@ -0,0 +1,31 @@
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// File next to the .song file that records where the map came from
+pub const SOURCE_FILE_NAME: &str = "conversion.json";
+
+/// Where a converted map came from and how it was converted. Credits the
+/// original creators and has everything needed to convert the map again.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Source {
+    pub beatmap_set_id: i32,
+    pub beatmap_id: i32,
+    pub url: String,
+    pub artist: String,
+    pub artist_unicode: String,
+    pub title: String,
+    pub title_unicode: String,
+    /// Where the song is from, like a game or anime
+    pub song_source: String,
+    pub tags: Vec<String>,
+    /// Creator of the osu! map
+    pub mapper: String,
+    /// Name of the osu! difficulty
+    pub difficulty: String,
+    /// MD5 of the .osu file, in hex like osu! uses it
+    pub osu_md5: String,
+    pub converter_version: String,
+    /// Options the map was converted with
+    pub options: Value,
+}
@@ -0,0 +1,565 @@
+use std::sync::Arc;
+
+use ab_glyph::{Font, FontRef, GlyphId, PxScale, ScaleFont};
+use image::{
+    imageops, imageops::FilterType, DynamicImage, GenericImageView, GrayImage, Rgba, RgbaImage,
+};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::audio;
+
+/// Longest side of the image the focus point is searched on
+const FOCUS_ANALYSIS_SIZE: u32 = 256;
+/// Size the background of blur-fill is blurred at, blurring is slow on big images
+const BLUR_SIZE: u32 = 128;
+
+/// Font the text on generated thumbnails is drawn in
+const FONT: &[u8] = include_bytes!("../public/s/font/Aller.ttf");
+/// Most lines the title of a generated thumbnail is wrapped to
+const TITLE_LINES: usize = 3;
+
+/// How the background becomes a square thumbnail
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ThumbMode {
+    /// Keep the aspect ratio, only shrink it
+    Keep,
+    /// Square from the centre
+    Centre,
+    /// Square around the most detailed part
+    Focus,
+    /// Whole image on a blurred, enlarged copy of itself
+    BlurFill,
+}
+
+/// Turns `img` into a thumbnail at most `size` pixels wide and high
+pub fn make_thumb(img: &DynamicImage, mode: ThumbMode, size: u32) -> DynamicImage {
+    let size = size.max(1);
+    match mode {
+        ThumbMode::Keep => {
+            if img.width() > size || img.height() > size {
+                img.resize(size, size, FilterType::Lanczos3)
+            } else {
+                img.clone()
+            }
+        }
+        ThumbMode::Centre => {
+            let side = img.width().min(img.height());
+            img.crop_imm(
+                (img.width() - side) / 2,
+                (img.height() - side) / 2,
+                side,
+                side,
+            )
+            .resize_exact(size, size, FilterType::Lanczos3)
+        }
+        ThumbMode::Focus => {
+            let side = img.width().min(img.height());
+            let (x, y) = focus_square(img);
+            img.crop_imm(x, y, side, side)
+                .resize_exact(size, size, FilterType::Lanczos3)
+        }
+        ThumbMode::BlurFill => {
+            let background = img
+                .resize_to_fill(BLUR_SIZE, BLUR_SIZE, FilterType::Triangle)
+                .blur(BLUR_SIZE as f32 / 20.)
+                .resize_exact(size, size, FilterType::Triangle);
+            let foreground = img.resize(size, size, FilterType::Lanczos3);
+            let mut thumb = background.to_rgba8();
+            imageops::overlay(
+                &mut thumb,
+                &foreground.to_rgba8(),
+                ((size - foreground.width()) / 2) as i64,
+                ((size - foreground.height()) / 2) as i64,
+            );
+            DynamicImage::ImageRgba8(thumb)
+        }
+    }
+}
+
+/// Thumbnail for a map without a background: the cover art in the tags of
+/// the audio if there is any, otherwise a generated picture
+pub fn fallback(
+    audio: &Arc<[u8]>,
+    audio_file_name: &str,
+    title: &str,
+    artist: &str,
+    difficulty: &str,
+    mode: ThumbMode,
+    size: u32,
+) -> DynamicImage {
+    cover(audio, audio_file_name, mode, size)
+        .unwrap_or_else(|| generated(title, artist, difficulty, size))
+}
+
+/// Thumbnail from the cover art in the tags of the audio, if it has any
+pub fn cover(
+    audio: &Arc<[u8]>,
+    audio_file_name: &str,
+    mode: ThumbMode,
+    size: u32,
+) -> Option<DynamicImage> {
+    let data = audio::cover_art(audio, audio_file_name)?;
+    let cover = image::load_from_memory(&data)
+        .map_err(|e| log::warn!("Could not decode cover art: {e}"))
+        .ok()?;
+    Some(make_thumb(&cover, mode, size))
+}
+
+/// Square picture with the title, artist and difficulty name on a gradient.
+/// The colours come from the title, so every difficulty of a song looks the
+/// same.
+pub fn generated(title: &str, artist: &str, difficulty: &str, size: u32) -> DynamicImage {
+    let size = size.max(1);
+    // FNV-1a
+    let hash = title.bytes().fold(0xcbf29ce484222325u64, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x100000001b3)
+    });
+    let hue = (hash % 360) as f32;
+    let from = hsv(hue, 0.65, 0.55);
+    let to = hsv(hue + 40. + (hash >> 16) as f32 % 100., 0.75, 0.25);
+    // Centre of the faint rings
+    let cx = ((hash >> 32) % 1000) as f32 / 1000. * size as f32;
+    let cy = ((hash >> 42) % 1000) as f32 / 1000. * size as f32;
+    let ring = size as f32 / 10.;
+
+    let mut img = RgbaImage::from_fn(size, size, |x, y| {
+        let t = (x + y) as f32 / (2 * size) as f32;
+        let d = ((x as f32 - cx).powi(2) + (y as f32 - cy).powi(2)).sqrt();
+        let shade = 1. + 0.06 * (d / ring * std::f32::consts::TAU).sin();
+        let channel = |i: usize| ((from[i] * (1. - t) + to[i] * t) * shade).clamp(0., 255.) as u8;
+        Rgba([channel(0), channel(1), channel(2), 255])
+    });
+
+    let width = size as f32 * 0.85;
+    let title_scale = size as f32 / 9.;
+    let small_scale = size as f32 / 16.;
+    let mut lines = wrap(title, title_scale, width);
+    lines.truncate(TITLE_LINES);
+    let line_height = title_scale * 1.1;
+    let block = lines.len() as f32 * line_height + small_scale * 1.5;
+    let mut y = (size as f32 - block) / 2.;
+    for line in &lines {
+        draw_centred(&mut img, line, title_scale, y, Rgba([255, 255, 255, 255]));
+        y += line_height;
+    }
+    draw_centred(
+        &mut img,
+        artist,
+        small_scale,
+        y + small_scale * 0.5,
+        Rgba([230, 230, 230, 255]),
+    );
+    draw_centred(
+        &mut img,
+        difficulty,
+        small_scale,
+        size as f32 - small_scale * 2.,
+        Rgba([230, 230, 230, 255]),
+    );
+    DynamicImage::ImageRgba8(img)
+}
+
+/// Text stamped on a thumbnail so songs with the same background can be
+/// told apart
+#[derive(Debug, Clone, PartialEq)]
+pub struct Overlay<'a> {
+    /// Name and star rating of every difficulty in the song
+    pub difficulties: Vec<(&'a str, f64)>,
+    pub mapper: &'a str,
+}
+
+/// `img` with an "osu! convert" badge in the top left corner and a band
+/// with the difficulty names, star ratings and mapper along the bottom.
+/// Characters the font has no glyph for are left out.
+pub fn overlay(img: &DynamicImage, overlay: &Overlay) -> DynamicImage {
+    let mut img = img.to_rgba8();
+    let (width, height) = img.dimensions();
+    let unit = width.min(height) as f32;
+    let margin = unit / 32.;
+    let white = Rgba([255, 255, 255, 255]);
+
+    let badge_scale = unit / 18.;
+    let badge = "osu! convert";
+    fill_rect(
+        &mut img,
+        margin,
+        margin,
+        text_width(badge, badge_scale) + badge_scale * 0.8,
+        badge_scale * 1.4,
+        Rgba([255, 102, 170, 220]),
+    );
+    draw_text(
+        &mut img,
+        badge,
+        badge_scale,
+        margin + badge_scale * 0.4,
+        margin + badge_scale * 0.2,
+        white,
+    );
+
+    // Smaller rows for more difficulties, so the band covers less of the image
+    let rows = overlay.difficulties.len();
+    let name_scale = unit / (11. + 3. * rows.saturating_sub(1) as f32);
+    let credit_scale = unit / 20.;
+    let band = rows as f32 * name_scale * 1.2 + credit_scale * 1.4 + margin;
+    let top = height as f32 - band;
+    fill_rect(&mut img, 0., top, width as f32, band, Rgba([0, 0, 0, 170]));
+
+    let mut y = top + margin / 2.;
+    for (difficulty, stars) in &overlay.difficulties {
+        let stars = format!("{stars:.1}*");
+        let stars_width = text_width(&stars, name_scale);
+        draw_text(
+            &mut img,
+            &stars,
+            name_scale,
+            width as f32 - margin - stars_width,
+            y,
+            Rgba([255, 214, 90, 255]),
+        );
+        let name = fit(
+            difficulty,
+            name_scale,
+            width as f32 - margin * 3. - stars_width,
+        );
+        draw_text(&mut img, &name, name_scale, margin, y, white);
+        y += name_scale * 1.2;
+    }
+    let credit = fit(
+        &format!("mapped by {}", overlay.mapper),
+        credit_scale,
+        width as f32 - margin * 2.,
+    );
+    draw_text(
+        &mut img,
+        &credit,
+        credit_scale,
+        margin,
+        y,
+        Rgba([220, 220, 220, 255]),
+    );
+    DynamicImage::ImageRgba8(img)
+}
+
+/// Blends `color` over the rectangle with its top left corner at (`x`, `y`)
+fn fill_rect(img: &mut RgbaImage, x: f32, y: f32, width: f32, height: f32, color: Rgba<u8>) {
+    let alpha = color[3] as f32 / 255.;
+    let x_end = ((x + width).round().max(0.) as u32).min(img.width());
+    let y_end = ((y + height).round().max(0.) as u32).min(img.height());
+    for py in (y.round().max(0.) as u32)..y_end {
+        for px in (x.round().max(0.) as u32)..x_end {
+            let pixel = img.get_pixel_mut(px, py);
+            for i in 0..3 {
+                pixel[i] = (pixel[i] as f32 * (1. - alpha) + color[i] as f32 * alpha).round() as u8;
+            }
+            pixel[3] = pixel[3].max(color[3]);
+        }
+    }
+}
+
+/// `text` shortened with an ellipsis until it is at most `width` pixels wide
+fn fit(text: &str, scale: f32, width: f32) -> String {
+    if text_width(text, scale) <= width {
+        return text.to_string();
+    }
+    let mut chars: Vec<char> = text.chars().collect();
+    while !chars.is_empty() {
+        chars.pop();
+        let shortened = format!("{}...", chars.iter().collect::<String>().trim_end());
+        if text_width(&shortened, scale) <= width {
+            return shortened;
+        }
+    }
+    String::new()
+}
+
+/// Draws `text` horizontally centred with a shadow, its top at `y`
+fn draw_centred(img: &mut RgbaImage, text: &str, scale: f32, y: f32, color: Rgba<u8>) {
+    let x = (img.width() as f32 - text_width(text, scale)) / 2.;
+    let offset = (scale / 20.).max(1.);
+    draw_text(
+        img,
+        text,
+        scale,
+        x + offset,
+        y + offset,
+        Rgba([0, 0, 0, 160]),
+    );
+    draw_text(img, text, scale, x, y, color);
+}
+
+fn font() -> FontRef<'static> {
+    FontRef::try_from_slice(FONT).expect("Bundled font is valid")
+}
+
+/// Glyphs of `text`, without the characters the font has none for instead
+/// of drawing them as empty boxes
+fn glyph_ids<'a, F: Font>(font: &'a F, text: &'a str) -> impl Iterator<Item = GlyphId> + 'a {
+    text.chars()
+        .map(|c| font.glyph_id(c))
+        .filter(|id| id.0 != 0)
+}
+
+/// Width of `text` in pixels at a height of `scale` pixels
+pub fn text_width(text: &str, scale: f32) -> f32 {
+    let font = font();
+    let scaled = font.as_scaled(PxScale::from(scale));
+    let mut previous = None;
+    let mut width = 0.;
+    for id in glyph_ids(&font, text) {
+        if let Some(previous) = previous {
+            width += scaled.kern(previous, id);
+        }
+        width += scaled.h_advance(id);
+        previous = Some(id);
+    }
+    width
+}
+
+/// Draws `text` with its top left corner at (`x`, `y`), blending it with
+/// the image by the coverage and alpha of each pixel
+pub fn draw_text(img: &mut RgbaImage, text: &str, scale: f32, x: f32, y: f32, color: Rgba<u8>) {
+    let font = font();
+    let scaled = font.as_scaled(PxScale::from(scale));
+    let mut caret = x;
+    let mut previous = None;
+    for id in glyph_ids(&font, text) {
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+        let glyph = id.with_scale_and_position(scale, ab_glyph::point(caret, y + scaled.ascent()));
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+
+        let Some(outline) = font.outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outline.px_bounds();
+        outline.draw(|gx, gy, coverage| {
+            let px = bounds.min.x as i32 + gx as i32;
+            let py = bounds.min.y as i32 + gy as i32;
+            if px < 0 || py < 0 || px >= img.width() as i32 || py >= img.height() as i32 {
+                return;
+            }
+            let alpha = coverage.clamp(0., 1.) * color[3] as f32 / 255.;
+            let pixel = img.get_pixel_mut(px as u32, py as u32);
+            for i in 0..3 {
+                pixel[i] = (pixel[i] as f32 * (1. - alpha) + color[i] as f32 * alpha).round() as u8;
+            }
+            pixel[3] = pixel[3].max((alpha * 255.) as u8);
+        });
+    }
+}
+
+/// Splits `text` into lines at most `width` pixels wide, breaking between
+/// words. Words longer than a line are left whole.
+pub fn wrap(text: &str, scale: f32, width: f32) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if text_width(&format!("{line} {word}"), scale) <= width => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_string()),
+        }
+    }
+    lines
+}
+
+/// RGB of a hue in degrees, saturation and value, from 0 to 255
+fn hsv(hue: f32, saturation: f32, value: f32) -> [f32; 3] {
+    let h = hue.rem_euclid(360.) / 60.;
+    let c = value * saturation;
+    let x = c * (1. - (h % 2. - 1.).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.),
+        1 => (x, c, 0.),
+        2 => (0., c, x),
+        3 => (0., x, c),
+        4 => (x, 0., c),
+        _ => (c, 0., x),
+    };
+    let m = value - c;
+    [(r + m) * 255., (g + m) * 255., (b + m) * 255.]
+}
+
+/// Top left corner of the square along the longer side of `img` with the
+/// most edge energy, where the subject of the image usually is
+fn focus_square(img: &DynamicImage) -> (u32, u32) {
+    let (width, height) = img.dimensions();
+    if width == height {
+        return (0, 0);
+    }
+
+    let small = img
+        .resize(
+            FOCUS_ANALYSIS_SIZE,
+            FOCUS_ANALYSIS_SIZE,
+            FilterType::Triangle,
+        )
+        .to_luma8();
+    let energy = edge_energy(&small);
+    let horizontal = width > height;
+    let (long, short) = if horizontal {
+        (small.width(), small.height())
+    } else {
+        (small.height(), small.width())
+    };
+
+    // Energy of every line across the long side, summed over a square window
+    let lines: Vec<u64> = (0..long)
+        .map(|i| {
+            (0..short)
+                .map(|j| {
+                    let (x, y) = if horizontal { (i, j) } else { (j, i) };
+                    energy[(y * small.width() + x) as usize] as u64
+                })
+                .sum()
+        })
+        .collect();
+    let window = short.min(long) as usize;
+    let best = (0..=lines.len() - window)
+        .max_by_key(|start| {
+            let sum: u64 = lines[*start..start + window].iter().sum();
+            // Prefer the centre between equal windows
+            (
+                sum,
+                std::cmp::Reverse(start.abs_diff((lines.len() - window) / 2)),
+            )
+        })
+        .unwrap_or(0);
+
+    let side = width.min(height);
+    let scaled = (best as f64 / long as f64 * width.max(height) as f64).round() as u32;
+    let start = scaled.min(width.max(height) - side);
+    if horizontal {
+        (start, 0)
+    } else {
+        (0, start)
+    }
+}
+
+/// Gradient magnitude of every pixel
+fn edge_energy(img: &GrayImage) -> Vec<u32> {
+    let (width, height) = img.dimensions();
+    let at = |x: u32, y: u32| img.get_pixel(x.min(width - 1), y.min(height - 1))[0] as i32;
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let dx = at(x + 1, y) - at(x.saturating_sub(1), y);
+            let dy = at(x, y + 1) - at(x, y.saturating_sub(1));
+            dx.unsigned_abs() + dy.unsigned_abs()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage};
+
+    use super::*;
+
+    #[test]
+    fn portrait_to_square() {
+        let img = DynamicImage::new_rgb8(300, 900);
+        for mode in [ThumbMode::Centre, ThumbMode::Focus, ThumbMode::BlurFill] {
+            let thumb = make_thumb(&img, mode, 128);
+            assert_eq!(thumb.dimensions(), (128, 128), "{mode:?}");
+        }
+        assert_eq!(
+            make_thumb(&img, ThumbMode::Keep, 128).dimensions(),
+            (43, 128)
+        );
+    }
+
+    #[test]
+    fn focus_finds_detail() {
+        // Flat image with a checkerboard near the right edge
+        let img = RgbImage::from_fn(1600, 900, |x, y| {
+            if x > 1200 && (x / 10 + y / 10) % 2 == 0 {
+                Rgb([255, 255, 255])
+            } else {
+                Rgb([40, 40, 40])
+            }
+        });
+        let (x, y) = focus_square(&DynamicImage::ImageRgb8(img));
+        assert_eq!(y, 0);
+        assert!(x >= 300, "square starts at {x}");
+    }
+
+    #[test]
+    fn generated_is_deterministic() {
+        let a = generated("Song title", "Artist", "Insane", 256);
+        let b = generated("Song title", "Artist", "Hard", 256);
+        assert_eq!(a.dimensions(), (256, 256));
+        // Same colours for the same title
+        assert_eq!(a.get_pixel(0, 0), b.get_pixel(0, 0));
+        assert_ne!(
+            a.get_pixel(0, 0),
+            generated("Other song", "Artist", "Insane", 256).get_pixel(0, 0)
+        );
+    }
+
+    #[test]
+    fn overlay_keeps_size_and_marks_corner() {
+        let img = DynamicImage::new_rgb8(300, 200);
+        let stamped = overlay(
+            &img,
+            &Overlay {
+                difficulties: vec![("A very long difficulty name that will not fit", 4.25)],
+                mapper: "Someone",
+            },
+        );
+        assert_eq!(stamped.dimensions(), (300, 200));
+        // Badge in the top left, band along the bottom over a black image
+        assert_ne!(stamped.get_pixel(8, 8), img.get_pixel(8, 8));
+        assert_eq!(stamped.get_pixel(150, 100)[0], 0);
+    }
+
+    /// Height of the band along the bottom of an overlay on a white image
+    fn band_height(stamp: &Overlay) -> u32 {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(200, 200, Rgb([255, 255, 255])));
+        let stamped = overlay(&img, stamp);
+        (0..200)
+            .rev()
+            .take_while(|y| stamped.get_pixel(199, *y)[0] < 255)
+            .count() as u32
+    }
+
+    #[test]
+    fn overlay_lists_every_difficulty() {
+        let one = band_height(&Overlay {
+            difficulties: vec![("Hard", 3.)],
+            mapper: "Someone",
+        });
+        let three = band_height(&Overlay {
+            difficulties: vec![("Hard", 3.), ("Easy", 1.), ("Normal", 2.)],
+            mapper: "Someone",
+        });
+        assert!(three > one, "{three} rows vs {one}");
+    }
+
+    #[test]
+    fn skips_missing_glyphs() {
+        assert_eq!(text_width("難易度", 30.), 0.);
+        assert_eq!(text_width("Hard 難", 30.), text_width("Hard ", 30.));
+    }
+
+    #[test]
+    fn shortens_long_text() {
+        let text = "A very long difficulty name";
+        let short = fit(text, 30., 150.);
+        assert!(short.ends_with("..."));
+        assert!(text_width(&short, 30.) <= 150.);
+        assert_eq!(fit("Hard", 30., 150.), "Hard");
+    }
+
+    #[test]
+    fn wraps_words() {
+        let lines = wrap("a long title that does not fit", 40., 200.);
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|l| text_width(l, 40.) <= 200.));
+        assert_eq!(lines.join(" "), "a long title that does not fit");
+    }
+}
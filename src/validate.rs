@@ -0,0 +1,447 @@
+use std::{
+    fmt::Display,
+    fs,
+    io::{Cursor, Read},
+    path::Path,
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Result};
+use serde_derive::Serialize;
+
+use crate::{
+    audio,
+    te::{self, MapData, Note, NoteType},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Severity {
+    /// Likely to break the song in game
+    Error,
+    /// Allowed, but probably not intended
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Issue {
+    pub severity: Severity,
+    /// Time in ms the issue is at, if it is about a single point in time
+    pub time: Option<f64>,
+    pub message: String,
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.severity)?;
+        if let Some(time) = self.time {
+            write!(f, " at {time:.0}ms")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+fn issue(severity: Severity, time: Option<f64>, message: String) -> Issue {
+    Issue {
+        severity,
+        time,
+        message,
+    }
+}
+
+/// Checks the song data for problems. Notes after the end of the audio are
+/// only checked if `audio_length` in ms is given.
+pub fn validate(data: &MapData, audio_length: Option<f64>) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    let mut charts = vec![(
+        data.difficulty_name.clone(),
+        data.left_disc_notes.clone(),
+        data.right_disc_notes.clone(),
+    )];
+    for d in &data.additional_difficulties {
+        let field = |key: &str| d[key].as_str().unwrap_or_default().to_string();
+        charts.push((
+            field("DifficultyName"),
+            field("LeftDiscNotes"),
+            field("RightDiscNotes"),
+        ));
+    }
+
+    for (name, left, right) in &charts {
+        for (disc, notes) in [("left", left), ("right", right)] {
+            match te::parse_notes(notes) {
+                Ok(notes) => {
+                    let prefix = format!("{name}, {disc} disc");
+                    validate_notes(data, audio_length, &prefix, &notes, &mut issues)
+                }
+                Err(e) => issues.push(issue(
+                    Severity::Error,
+                    None,
+                    format!("{name}, {disc} disc: {e}"),
+                )),
+            }
+        }
+    }
+
+    for w in data.timing_points.windows(2) {
+        if w[1].time <= w[0].time {
+            issues.push(issue(
+                Severity::Error,
+                Some(w[1].time),
+                format!(
+                    "Timing point at {:.0}ms isn't after the one at {:.0}ms",
+                    w[1].time, w[0].time
+                ),
+            ));
+        }
+    }
+    for p in &data.timing_points {
+        if p.bpm <= 0. {
+            issues.push(issue(
+                Severity::Error,
+                Some(p.time),
+                format!("Timing point has invalid BPM {}", p.bpm),
+            ));
+        }
+    }
+    if data.bpm <= 0. {
+        issues.push(issue(
+            Severity::Error,
+            None,
+            format!("Invalid BPM {}", data.bpm),
+        ));
+    }
+
+    let sections = data
+        .breaks
+        .iter()
+        .map(|b| ("Break", Severity::Error, b.start_time, b.end_time))
+        .chain(data.special_sections.iter().map(|s| {
+            (
+                "Special section",
+                Severity::Warning,
+                s.start_time,
+                s.end_time,
+            )
+        }));
+    let all_notes: Vec<Note> = charts
+        .first()
+        .into_iter()
+        .flat_map(|(_, l, r)| [l, r])
+        .filter_map(|n| te::parse_notes(n).ok())
+        .flatten()
+        .collect();
+    for (kind, severity, start, end) in sections {
+        if end < start {
+            issues.push(issue(
+                Severity::Error,
+                Some(start),
+                format!("{kind} ends before it starts"),
+            ));
+        }
+        if let Some(n) = all_notes.iter().find(|n| start <= n.time && n.time <= end) {
+            issues.push(issue(
+                severity,
+                Some(n.time),
+                format!("{kind} from {start:.0}ms to {end:.0}ms contains notes"),
+            ));
+        }
+    }
+
+    issues
+}
+
+fn validate_notes(
+    data: &MapData,
+    audio_length: Option<f64>,
+    prefix: &str,
+    notes: &[Note],
+    issues: &mut Vec<Issue>,
+) {
+    for w in notes.windows(2) {
+        if w[1].time < w[0].time {
+            issues.push(issue(
+                Severity::Error,
+                Some(w[1].time),
+                format!("{prefix}: note comes after a later note"),
+            ));
+        }
+    }
+
+    let mut hold_start = None;
+    for n in notes {
+        match n.note_type {
+            NoteType::HoldStart => {
+                if hold_start.is_some() {
+                    issues.push(issue(
+                        Severity::Error,
+                        Some(n.time),
+                        format!("{prefix}: hold starts before the previous one ended"),
+                    ));
+                }
+                hold_start = Some(n.time);
+            }
+            NoteType::HoldEnd => {
+                if hold_start.take().is_none() {
+                    issues.push(issue(
+                        Severity::Error,
+                        Some(n.time),
+                        format!("{prefix}: hold end without a hold start"),
+                    ));
+                }
+            }
+            NoteType::Normal | NoteType::Reverse => {
+                if hold_start.is_some() {
+                    issues.push(issue(
+                        Severity::Warning,
+                        Some(n.time),
+                        format!("{prefix}: note during a hold"),
+                    ));
+                }
+            }
+        }
+    }
+    if let Some(start) = hold_start {
+        issues.push(issue(
+            Severity::Error,
+            Some(start),
+            format!("{prefix}: hold never ends"),
+        ));
+    }
+
+    if let Some(n) = notes
+        .iter()
+        .find(|n| n.time < data.first_beat_offset_in_ms as f64)
+    {
+        issues.push(issue(
+            Severity::Error,
+            Some(n.time),
+            format!("{prefix}: note before the first beat"),
+        ));
+    }
+    if let Some(length) = audio_length {
+        if let Some(n) = notes.iter().find(|n| n.time > length) {
+            issues.push(issue(
+                Severity::Error,
+                Some(n.time),
+                format!("{prefix}: note after the end of the audio"),
+            ));
+        }
+    }
+}
+
+/// Checks that the files the song refers to are in `files`
+pub fn validate_files<S: AsRef<str>>(data: &MapData, files: &[S]) -> Vec<Issue> {
+    [
+        ("Audio", &data.audio_file_name),
+        ("Thumbnail", &data.thumbnail_file_name),
+    ]
+    .into_iter()
+    .filter(|(_, name)| !files.iter().any(|f| f.as_ref() == name.as_str()))
+    .map(|(kind, name)| {
+        issue(
+            Severity::Error,
+            None,
+            format!("{kind} file {name:?} is missing"),
+        )
+    })
+    .collect()
+}
+
+/// Validates the .song file in a zip and the files it refers to
+pub fn validate_zip(zip: &[u8]) -> Result<Vec<Issue>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(zip))?;
+    let files: Vec<String> = archive.file_names().map(str::to_string).collect();
+    let song_file = files
+        .iter()
+        .find(|f| f.ends_with(".song"))
+        .ok_or_else(|| anyhow!("Could not find .song file"))?;
+
+    let mut song = String::new();
+    archive.by_name(song_file)?.read_to_string(&mut song)?;
+    let data: MapData = serde_json::from_str(&song)?;
+
+    let mut audio = Vec::new();
+    let audio = match archive.by_name(&data.audio_file_name) {
+        Ok(mut file) => file.read_to_end(&mut audio).ok().map(|_| audio),
+        Err(_) => None,
+    };
+    Ok(validate_song(&data, &files, audio.map(Arc::from)))
+}
+
+/// Validates the .song file in a song folder and the files it refers to
+pub fn validate_dir<P: AsRef<Path>>(dir: P) -> Result<Vec<Issue>> {
+    let files: Vec<String> = dir
+        .as_ref()
+        .read_dir()?
+        .filter_map(|f| f.ok()?.file_name().into_string().ok())
+        .collect();
+    let song_file = files
+        .iter()
+        .find(|f| f.ends_with(".song"))
+        .ok_or_else(|| anyhow!("Could not find .song file"))?;
+
+    let data: MapData = serde_json::from_str(&fs::read_to_string(dir.as_ref().join(song_file))?)?;
+
+    let audio = fs::read(dir.as_ref().join(&data.audio_file_name)).ok();
+    Ok(validate_song(&data, &files, audio.map(Arc::from)))
+}
+
+/// Validates song data read from a package with `files`, including the
+/// length of its audio file if it could be read
+fn validate_song<S: AsRef<str>>(
+    data: &MapData,
+    files: &[S],
+    audio: Option<Arc<[u8]>>,
+) -> Vec<Issue> {
+    let audio_length = audio.and_then(|a| match audio::length(&a, &data.audio_file_name) {
+        Ok(length) => Some(length),
+        Err(e) => {
+            log::warn!("Could not read the length of the audio: {e}");
+            None
+        }
+    });
+    let mut issues = validate(data, audio_length);
+    issues.extend(validate_files(data, files));
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::ZipWriter;
+
+    use super::*;
+    use crate::{
+        audio::Pcm,
+        te::{Break, TimingPoint},
+    };
+
+    fn data(left: &str) -> MapData {
+        MapData {
+            difficulty_name: "Hard".to_string(),
+            bpm: 120.,
+            audio_file_name: "audio.wav".to_string(),
+            thumbnail_file_name: "thumb.png".to_string(),
+            left_disc_notes: left.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn messages(issues: &[Issue]) -> Vec<String> {
+        issues.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn valid_song_has_no_issues() {
+        let data = data("0:0:0|500:2:0|1000:3:0");
+        assert_eq!(
+            messages(&validate(&data, Some(2000.))),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn finds_unsorted_notes() {
+        let data = data("500:0:0|0:0:0");
+        assert_eq!(
+            messages(&validate(&data, None)),
+            vec!["Error at 0ms: Hard, left disc: note comes after a later note"]
+        );
+    }
+
+    #[test]
+    fn finds_unmatched_holds() {
+        let data = data("0:3:0|500:2:0");
+        assert_eq!(
+            messages(&validate(&data, None)),
+            vec![
+                "Error at 0ms: Hard, left disc: hold end without a hold start",
+                "Error at 500ms: Hard, left disc: hold never ends",
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_notes_before_the_first_beat() {
+        let data = MapData {
+            first_beat_offset_in_ms: 100,
+            ..data("50:0:0|500:0:0")
+        };
+        assert_eq!(
+            messages(&validate(&data, None)),
+            vec!["Error at 50ms: Hard, left disc: note before the first beat"]
+        );
+    }
+
+    #[test]
+    fn finds_unordered_timing_points() {
+        let point = |time| TimingPoint {
+            time,
+            bpm: 120.,
+            ..Default::default()
+        };
+        let data = MapData {
+            timing_points: vec![point(1000.), point(1000.)],
+            ..data("")
+        };
+        assert_eq!(
+            messages(&validate(&data, None)),
+            vec!["Error at 1000ms: Timing point at 1000ms isn't after the one at 1000ms"]
+        );
+    }
+
+    #[test]
+    fn finds_notes_in_breaks() {
+        let data = MapData {
+            breaks: vec![Break {
+                start_time: 400.,
+                end_time: 800.,
+                ..Default::default()
+            }],
+            ..data("0:0:0|500:0:0")
+        };
+        assert_eq!(
+            messages(&validate(&data, None)),
+            vec!["Error at 500ms: Break from 400ms to 800ms contains notes"]
+        );
+    }
+
+    #[test]
+    fn finds_missing_files() {
+        let issues = validate_files(&data(""), &["audio.wav", "Song.song"]);
+        assert_eq!(
+            messages(&issues),
+            vec!["Error: Thumbnail file \"thumb.png\" is missing"]
+        );
+    }
+
+    #[test]
+    fn finds_notes_after_the_audio() -> Result<()> {
+        let data = data("0:0:0|1500:0:0");
+        let audio = Pcm {
+            sample_rate: 8000,
+            channels: 1,
+            samples: vec![0.; 8000],
+        }
+        .to_wav()?;
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in [
+            ("Song.song", data.to_json()?.into_bytes()),
+            ("audio.wav", audio),
+            ("thumb.png", Vec::new()),
+        ] {
+            zip.start_file(name, Default::default())?;
+            zip.write_all(&content)?;
+        }
+        let zip = zip.finish()?.into_inner();
+
+        assert_eq!(
+            messages(&validate_zip(&zip)?),
+            vec!["Error at 1500ms: Hard, left disc: note after the end of the audio"]
+        );
+        Ok(())
+    }
+}
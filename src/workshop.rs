@@ -0,0 +1,298 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{Cursor, Seek, Write},
+    path::Path,
+};
+
+use anyhow::Result;
+use image::{codecs::png::PngEncoder, ColorType, DynamicImage, ImageEncoder, ImageFormat};
+use zip::ZipWriter;
+
+use crate::{
+    convert::{Options, SpinnerBehaviour},
+    overlap::OverlapPolicy,
+    rating,
+    te::{self, ZipOptions},
+    thumb::{self, ThumbMode},
+};
+
+/// Folder of the bundle the song files go in
+const SONG_DIR: &str = "song";
+const PREVIEW_FILE_NAME: &str = "preview.png";
+const DESCRIPTION_FILE_NAME: &str = "description.txt";
+/// Width and height of the preview image, Steam shows it at most this big
+const PREVIEW_SIZE: u32 = 512;
+
+/// Square preview image of the map for the workshop page
+pub fn preview(map: &te::Map) -> DynamicImage {
+    thumb::make_thumb(&map.thumb, ThumbMode::Centre, PREVIEW_SIZE)
+}
+
+/// Workshop description in Steam's BBCode, crediting the song and the osu!
+/// map it was converted from
+pub fn description(map: &te::Map) -> Result<String> {
+    let data = &map.data;
+    let mut out = String::new();
+    writeln!(
+        out,
+        "[h1]{} - {}[/h1]",
+        escape(&data.artist),
+        escape(&data.display_name)
+    )?;
+
+    writeln!(out, "[h2]Difficulties[/h2]\n[list]")?;
+    writeln!(
+        out,
+        "[*]{} ({})",
+        escape(&data.difficulty_name),
+        rating::rate_map(data)?.label()
+    )?;
+    for difficulty in &data.additional_difficulties {
+        if let Some(name) = difficulty["DifficultyName"].as_str() {
+            writeln!(out, "[*]{}", escape(name))?;
+        }
+    }
+    writeln!(out, "[/list]")?;
+
+    writeln!(out, "[h2]Credits[/h2]\n[list]")?;
+    match &map.source {
+        Some(source) => {
+            writeln!(
+                out,
+                "[*]Artist: {}",
+                escape(&with_unicode(&source.artist, &source.artist_unicode))
+            )?;
+            writeln!(
+                out,
+                "[*]Title: {}",
+                escape(&with_unicode(&source.title, &source.title_unicode))
+            )?;
+            if !source.song_source.is_empty() {
+                writeln!(out, "[*]Source: {}", escape(&source.song_source))?;
+            }
+            writeln!(
+                out,
+                "[*]osu! map: [url={}]{}[/url] by {}",
+                source.url,
+                escape(&format!("{} [{}]", source.title, source.difficulty)),
+                escape(&source.mapper)
+            )?;
+            writeln!(
+                out,
+                "[*]Converted with osu!te {}",
+                escape(&source.converter_version)
+            )?;
+        }
+        None => {
+            writeln!(out, "[*]Artist: {}", escape(&data.artist))?;
+            writeln!(out, "[*]Charted by {}", escape(&data.mapper_name))?;
+        }
+    }
+    writeln!(out, "[/list]")?;
+
+    if let Some(source) = &map.source {
+        // Options of older packages that can't be read are left out
+        if let Ok(options) = serde_json::from_value::<Options>(source.options.clone()) {
+            writeln!(out, "[h2]Conversion options[/h2]\n[list]")?;
+            for (label, value) in option_lines(&options) {
+                writeln!(out, "[*]{label}: {value}")?;
+            }
+            writeln!(out, "[/list]")?;
+        }
+    }
+
+    if let Some(source) = map.source.as_ref().filter(|s| !s.tags.is_empty()) {
+        writeln!(out, "Tags: {}", escape(&source.tags.join(" ")))?;
+    }
+    Ok(out)
+}
+
+/// Conversion options that change how the song plays, labelled like in the
+/// form. Thumbnail and packaging options don't matter to players.
+fn option_lines(options: &Options) -> Vec<(&'static str, String)> {
+    let yes_no = |b: bool| if b { "Yes" } else { "No" }.to_string();
+    let mut lines = vec![
+        (
+            "Slider limit",
+            format!("{} beats", options.slider_beat_limit),
+        ),
+        (
+            "Spinners",
+            match options.spinner_behaviour {
+                SpinnerBehaviour::Ignore => "Ignore",
+                SpinnerBehaviour::Current => "Slider on current",
+                SpinnerBehaviour::Both => "Slider on both",
+            }
+            .to_string(),
+        ),
+        (
+            "Overlapping notes",
+            match options.overlap_policy {
+                OverlapPolicy::Keep => "Keep",
+                OverlapPolicy::Truncate => "Shorten slider",
+                OverlapPolicy::MoveToOtherDisc => "Move to other circle",
+                OverlapPolicy::Drop => "Remove",
+            }
+            .to_string(),
+        ),
+    ];
+
+    // Hitsound bits of osu!, like the mask toggles of the form
+    let reverse: Vec<&str> = [(1, "normal"), (2, "whistle"), (4, "finish"), (8, "clap")]
+        .into_iter()
+        .filter(|(bit, _)| options.reverse_hitsound_mask & bit != 0)
+        .map(|(_, name)| name)
+        .collect();
+    lines.push((
+        "Reverse notes on",
+        if reverse.is_empty() {
+            "Nothing".to_string()
+        } else {
+            reverse.join(", ")
+        },
+    ));
+    if !options.snap_divisors.is_empty() {
+        let divisors: Vec<String> = options
+            .snap_divisors
+            .iter()
+            .map(|d| format!("1/{d}"))
+            .collect();
+        lines.push(("Snap to beat", divisors.join(", ")));
+    }
+    if options.rate != 1. {
+        lines.push(("Rate", format!("{}x", options.rate)));
+        lines.push(("Keep pitch", yes_no(options.keep_pitch)));
+    }
+    if options.global_offset != 0. {
+        lines.push(("Offset", format!("{:+} ms", options.global_offset)));
+    }
+    lines.push(("Easier difficulties", yes_no(options.generate_easier)));
+    lines.push(("Hitsounds in audio", yes_no(options.bake_hitsounds)));
+    lines
+}
+
+/// `text` with its square brackets kept from being read as BBCode tags
+fn escape(text: &str) -> String {
+    text.replace('[', "[noparse][[/noparse]")
+}
+
+/// `name` followed by its original spelling if that is different
+fn with_unicode(name: &str, unicode: &str) -> String {
+    if unicode.is_empty() || unicode == name {
+        name.to_string()
+    } else {
+        format!("{name} ({unicode})")
+    }
+}
+
+/// Writes the workshop bundle of `map` as a zip to `writer`: the song files
+/// in a folder, a preview image and the description
+pub fn write_bundle<W: Write + Seek>(map: &te::Map, writer: W, options: ZipOptions) -> Result<W> {
+    let mut writer = ZipWriter::new(writer);
+    map.write_files(&mut writer, SONG_DIR, options)?;
+
+    writer.start_file(PREVIEW_FILE_NAME, options.file_options())?;
+    let preview = preview(map).to_rgba8();
+    PngEncoder::new(&mut writer).write_image(
+        &preview,
+        preview.width(),
+        preview.height(),
+        ColorType::Rgba8,
+    )?;
+    writer.start_file(DESCRIPTION_FILE_NAME, options.file_options())?;
+    writer.write_all(description(map)?.as_bytes())?;
+
+    Ok(writer.finish()?)
+}
+
+pub fn bundle_zip(map: &te::Map, options: ZipOptions) -> Result<Vec<u8>> {
+    Ok(write_bundle(map, Cursor::new(Vec::new()), options)?.into_inner())
+}
+
+/// Writes the workshop bundle of `map` into the folder `dir`
+pub fn save_bundle<P: AsRef<Path>>(map: &te::Map, dir: P) -> Result<()> {
+    let dir = dir.as_ref();
+    map.save_dir(dir.join(SONG_DIR))?;
+    preview(map).save_with_format(dir.join(PREVIEW_FILE_NAME), ImageFormat::Png)?;
+    fs::write(dir.join(DESCRIPTION_FILE_NAME), description(map)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::te::{MapData, Source};
+
+    #[test]
+    fn bundle_has_credits() {
+        let map = te::Map {
+            data: MapData {
+                song_file_name: "Song.song".to_string(),
+                audio_file_name: "audio.mp3".to_string(),
+                thumbnail_file_name: "thumb.png".to_string(),
+                artist: "Artist".to_string(),
+                display_name: "Song [TV Size]".to_string(),
+                difficulty_name: "Hard".to_string(),
+                ..Default::default()
+            },
+            audio: vec![0; 100].into(),
+            thumb: DynamicImage::new_rgb8(16, 9),
+            source: Some(Source {
+                title: "Song [TV Size]".to_string(),
+                title_unicode: "歌".to_string(),
+                difficulty: "Hard".to_string(),
+                song_source: "Some game".to_string(),
+                mapper: "Mapper".to_string(),
+                url: "https://osu.ppy.sh/beatmapsets/1#osu/2".to_string(),
+                options: json!({
+                    "slider_beat_limit": 0.5,
+                    "reverse_hitsound_mask": 0b1010,
+                    "overlap_policy": "MoveToOtherDisc",
+                    "rate": 1.5,
+                }),
+                ..Default::default()
+            }),
+        };
+
+        let description = description(&map).unwrap();
+        assert!(description.contains("[h1]Artist - Song [noparse][[/noparse]TV Size][/h1]"));
+        assert!(description.contains("Song [noparse][[/noparse]TV Size] (歌)"));
+        assert!(description.contains("Source: Some game"));
+        assert!(description.contains(
+            "[url=https://osu.ppy.sh/beatmapsets/1#osu/2]Song [noparse][[/noparse]TV Size] [noparse][[/noparse]Hard][/url] by Mapper"
+        ));
+        assert!(description.contains("[*]Slider limit: 0.5 beats"));
+        assert!(description.contains("[*]Reverse notes on: whistle, clap"));
+        assert!(description.contains("[*]Overlapping notes: Move to other circle"));
+        assert!(description.contains("[*]Rate: 1.5x"));
+        assert!(!description.contains("reverse_hitsound_mask"));
+        assert!(!description.contains("Thumbnail"));
+
+        let options = Options {
+            reverse_hitsound_mask: 0b0011,
+            ..Default::default()
+        };
+        assert!(
+            option_lines(&options).contains(&("Reverse notes on", "normal, whistle".to_string()))
+        );
+
+        let zip = bundle_zip(&map, ZipOptions::default()).unwrap();
+        let archive = zip::ZipArchive::new(Cursor::new(zip)).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "description.txt",
+                "preview.png",
+                "song/Song.song",
+                "song/audio.mp3",
+                "song/conversion.json",
+                "song/thumb.png"
+            ]
+        );
+    }
+}